@@ -0,0 +1,300 @@
+use crate::experiment::Experiment;
+use crate::runner::Runner;
+use crate::snapshot::SNAPSHOT_FILE;
+use crate::timings::UNITS_FILE;
+use crate::toggle::{Linker, Toggle};
+use crate::{linker, parse, read_project_file, CARGO_TOML, CONFIG_TOML};
+use duct::cmd;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use toml_edit::Item;
+
+/// The outcome of one preflight check.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Check {
+    pub description: String,
+    /// What to do about it, if the check failed.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn new(description: impl Into<String>, fix: Option<String>) -> Self {
+        Check {
+            description: description.into(),
+            fix,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.fix.is_none()
+    }
+}
+
+/// Whether `program` is an executable in `PATH`, or a path to one.
+fn on_path(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+fn read(cargo_dir: &str, file: &str) -> Option<String> {
+    fs::read_to_string(Path::new(cargo_dir).join(file)).ok()
+}
+
+/// Validates everything the experiment will need in `cargo_dir`, without changing anything.
+/// Only the toggles some configuration enables are checked.
+pub fn check(experiment: &Experiment, cargo_dir: &str) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    checks.push(Check::new(
+        format!("{CARGO_TOML} exists and parses"),
+        match read(cargo_dir, CARGO_TOML).map(|c| parse(&c)) {
+            None => Some(format!("run customs with --cargo-dir pointing at the project, no {CARGO_TOML} in {cargo_dir}")),
+            Some(Err(e)) => Some(format!("fix the syntax of {CARGO_TOML}: {e}")),
+            Some(Ok(_)) => None,
+        },
+    ));
+    checks.push(Check::new(
+        "no session was interrupted before restoring the project",
+        Path::new(cargo_dir)
+            .join(SNAPSHOT_FILE)
+            .exists()
+            .then(|| format!("run `customs restore --cargo-dir {cargo_dir}` to put back the files of the last session")),
+    ));
+
+    let program = experiment
+        .cargo_command
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    checks.push(Check::new(
+        format!("`{program}` from cargo_command is installed"),
+        (!on_path(program)).then(|| format!("install `{program}` or change cargo_command")),
+    ));
+    let hyperfine = experiment.runner == Runner::Hyperfine
+        || experiment
+            .scenarios
+            .iter()
+            .any(|s| s.runner == Some(Runner::Hyperfine));
+    if hyperfine {
+        checks.push(Check::new(
+            "hyperfine is installed",
+            (!on_path("hyperfine")).then(|| {
+                "run `cargo install hyperfine`, or set runner = \"native\" in the experiment file"
+                    .to_string()
+            }),
+        ));
+    }
+
+    if let Some(run) = experiment.hot_dependencies_run() {
+        let units = Path::new(&experiment.output_dir)
+            .join(format!("{run}.timings"))
+            .join(UNITS_FILE);
+        checks.push(Check::new(
+            format!("{run} has timings to pick the heaviest dependencies from"),
+            (!units.exists()).then(|| {
+                "run a session with `timings = true` first, or list the dependencies in `packages` of [hot_dependencies]".to_string()
+            }),
+        ));
+    }
+
+    let (toggles, states) = match experiment
+        .resolve_toggles()
+        .and_then(|t| Ok((t, experiment.toggle_states()?)))
+    {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            checks.push(Check::new(
+                "the experiment's toggles can be resolved",
+                Some(format!("fix the experiment or the project: {e}")),
+            ));
+            return checks;
+        }
+    };
+    let enabled: BTreeSet<String> = states
+        .iter()
+        .flat_map(|s| {
+            s.enabled()
+                .map(|t| t.name().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let rustc = cmd!("rustc", "-vV")
+        .dir(cargo_dir)
+        .stderr_null()
+        .read()
+        .unwrap_or_default();
+    let nightly = rustc
+        .lines()
+        .any(|l| l.starts_with("release:") && (l.contains("nightly") || l.contains("dev")));
+
+    // Without its timings, `hot-deps` has no packages yet, which is reported above
+    let unresolved = experiment
+        .hot_dependencies
+        .as_ref()
+        .is_some_and(|h| h.packages.is_empty());
+    for toggle in &toggles {
+        if toggle.name() == "hot-deps" && unresolved {
+            continue;
+        }
+        if enabled.contains(toggle.name()) {
+            checks.push(check_plan(toggle.as_ref(), cargo_dir));
+        }
+    }
+    let parallel = toggles
+        .iter()
+        .any(|t| t.group() == Some("threads") && enabled.contains(t.name()));
+    if parallel
+        || ["cranelift", "rust-lld"]
+            .iter()
+            .any(|name| enabled.contains(*name))
+    {
+        checks.push(Check::new(
+            "the project builds with a nightly toolchain, for -Z flags and cranelift",
+            (!nightly).then(|| {
+                "add a rust-toolchain.toml with `channel = \"nightly\"` to the project, or run `rustup override set nightly` in it".to_string()
+            }),
+        ));
+    }
+    if enabled.contains("cranelift") {
+        checks.extend(check_cranelift(cargo_dir));
+    }
+    let linkers = [
+        Linker::MOLD,
+        Linker::LLD,
+        Linker::GOLD,
+        Linker::WILD,
+        Linker::RUST_LLD,
+    ];
+    let linkers: Vec<&Linker> = linkers
+        .iter()
+        .filter(|l| enabled.contains(l.name()))
+        .collect();
+    if !linkers.is_empty() {
+        checks.extend(check_linkers(&linkers, cargo_dir));
+    }
+    checks
+}
+
+/// Enabling the toggle has to be possible and has to actually switch it on,
+/// otherwise its runs would silently measure the default configuration.
+fn check_plan(toggle: &dyn Toggle, cargo_dir: &str) -> Check {
+    let description = format!("{} can be enabled in {}", toggle.name(), toggle.file());
+    // Toggles create a missing file, so it counts as empty
+    let enabled = read_project_file(cargo_dir, toggle.file())
+        .and_then(|contents| toggle.plan(true, &contents))
+        .and_then(|planned| toggle.is_enabled_in(&planned));
+    let fix = match (toggle.name(), enabled) {
+        (_, Ok(true)) => None,
+        (_, Err(e)) => Some(format!("fix {}: {e}", toggle.file())),
+        ("cranelift", Ok(false)) => Some(format!(
+            "add a [profile.server-dev] section to {}",
+            toggle.file()
+        )),
+        (name, Ok(false)) => Some(format!("{name} has no effect on {}", toggle.file())),
+    };
+    Check::new(description, fix)
+}
+
+fn check_cranelift(cargo_dir: &str) -> Vec<Check> {
+    let components = cmd!("rustup", "component", "list", "--installed")
+        .dir(cargo_dir)
+        .stderr_null()
+        .read()
+        .unwrap_or_default();
+    let config = read(cargo_dir, CONFIG_TOML)
+        .and_then(|c| parse(&c).ok())
+        .unwrap_or_default();
+    let unstable = config
+        .get("unstable")
+        .and_then(|u| u.get("codegen-backend"))
+        .and_then(Item::as_bool);
+    vec![
+        Check::new(
+            "the cranelift codegen backend is installed",
+            (!components
+                .lines()
+                .any(|c| c.starts_with("rustc-codegen-cranelift")))
+            .then(|| {
+                "run `rustup component add rustc-codegen-cranelift-preview --toolchain nightly`"
+                    .to_string()
+            }),
+        ),
+        Check::new(
+            format!("[unstable] codegen-backend is enabled in {CONFIG_TOML}"),
+            (unstable != Some(true)).then(|| {
+                format!("add `codegen-backend = true` to the [unstable] section of {CONFIG_TOML}")
+            }),
+        ),
+    ]
+}
+
+fn check_linkers(linkers: &[&Linker], cargo_dir: &str) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for linker in linkers {
+        if let Some(program) = linker.program() {
+            checks.push(Check::new(
+                format!("{program} is installed for the {} toggle", linker.name()),
+                (!on_path(program)).then(|| format!("install {program}")),
+            ));
+        }
+    }
+
+    // The linker driver the flags are passed to
+    let driver = read(cargo_dir, CONFIG_TOML)
+        .and_then(|c| linker(&c).ok().flatten())
+        .unwrap_or_else(|| "cc".to_string());
+    checks.push(Check::new(
+        format!("the linker driver `{driver}` is installed"),
+        (!on_path(&driver)).then(|| format!("install {driver}")),
+    ));
+    for linker in linkers {
+        if let Some(driver) = linker.driver() {
+            checks.push(Check::new(
+                format!(
+                    "{driver} is installed to drive the {} toggle",
+                    linker.name()
+                ),
+                (!on_path(driver)).then(|| format!("install {driver}")),
+            ));
+        }
+    }
+    if linkers.iter().any(|l| l.name() == Linker::RUST_LLD.name()) {
+        checks.push(check_rustc_accepts(&Linker::RUST_LLD, cargo_dir));
+    }
+    checks
+}
+
+/// Links an empty program with the linker's flags, using the project's toolchain.
+fn check_rustc_accepts(linker: &Linker, cargo_dir: &str) -> Check {
+    let out = std::env::temp_dir().join(format!("customs-doctor-{}", linker.name()));
+    let mut args: Vec<String> = linker.flags().iter().map(|f| f.to_string()).collect();
+    args.extend(["-".to_string(), "-o".to_string()]);
+    args.push(out.display().to_string());
+    let accepted = cmd("rustc", args)
+        .stdin_bytes("fn main() {}")
+        .dir(cargo_dir)
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run();
+    let _ = fs::remove_file(&out);
+    Check::new(
+        format!("the project's rustc links with the {} flags", linker.name()),
+        match accepted {
+            Ok(output) if output.status.success() => None,
+            Ok(output) => Some(format!(
+                "use a nightly that supports `{}`: {}",
+                linker.flags().join(" "),
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+            )),
+            Err(e) => Some(format!("install rustc: {e}")),
+        },
+    )
+}
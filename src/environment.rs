@@ -0,0 +1,179 @@
+use duct::cmd;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+
+/// Written next to the hyperfine exports so results can be traced back to a toolchain.
+pub const ENVIRONMENT_FILE: &str = "environment.json";
+
+/// The machine and toolchain a session was measured on. Everything after `arch`
+/// is missing from sessions recorded by older versions of customs.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Environment {
+    /// Output of `rustc -vV`
+    pub rustc: String,
+    /// Output of `cargo -V`
+    pub cargo: String,
+    pub os: String,
+    pub arch: String,
+    /// `commit-date` of a nightly or dev toolchain
+    pub nightly_date: Option<String>,
+    /// Output of `mold --version`
+    pub mold: Option<String>,
+    /// The installed cranelift component, with the release it ships with
+    pub cranelift: Option<String>,
+    /// Output of `hyperfine --version`
+    pub hyperfine: Option<String>,
+    pub cpu: Option<String>,
+    /// Logical cores available to the build
+    pub cores: Option<usize>,
+    /// Total memory in bytes
+    pub memory: Option<u64>,
+    /// Output of `uname -sr`
+    pub kernel: Option<String>,
+}
+
+/// The first line a tool prints, or `None` if it isn't installed or fails.
+fn version(program: &str, args: &[&str], dir: &str) -> Option<String> {
+    let output = cmd(program, args).dir(dir).stderr_null().read().ok()?;
+    output.lines().next().map(|l| l.trim().to_string())
+}
+
+/// The `key: value` line of `rustc -vV` output.
+fn rustc_field<'a>(rustc: &'a str, key: &str) -> Option<&'a str> {
+    rustc
+        .lines()
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix(':'))
+        .map(str::trim)
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_and_memory() -> (Option<String>, Option<u64>) {
+    let field = |file: &str, key: &str| {
+        let contents = fs::read_to_string(file).ok()?;
+        contents
+            .lines()
+            .find(|l| l.starts_with(key))
+            .and_then(|l| l.split_once(':'))
+            .map(|(_, v)| v.trim().to_string())
+    };
+    let memory = field("/proc/meminfo", "MemTotal")
+        .and_then(|kb| kb.trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024);
+    (field("/proc/cpuinfo", "model name"), memory)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_and_memory() -> (Option<String>, Option<u64>) {
+    let sysctl = |key: &str| version("sysctl", &["-n", key], ".");
+    (
+        sysctl("machdep.cpu.brand_string"),
+        sysctl("hw.memsize").and_then(|m| m.parse().ok()),
+    )
+}
+
+/// Queries the toolchain from inside `cargo_dir`, so a `rust-toolchain.toml` there is honored.
+pub fn capture(cargo_dir: &str) -> Result<Environment, Error> {
+    let rustc = cmd!("rustc", "-vV").dir(cargo_dir).read()?;
+    let release = rustc_field(&rustc, "release")
+        .unwrap_or_default()
+        .to_string();
+    let nightly_date = match release.contains("nightly") || release.contains("dev") {
+        true => rustc_field(&rustc, "commit-date").map(String::from),
+        false => None,
+    };
+    let cranelift = cmd!("rustup", "component", "list", "--installed")
+        .dir(cargo_dir)
+        .stderr_null()
+        .read()
+        .ok()
+        .and_then(|components| {
+            components
+                .lines()
+                .find(|c| c.starts_with("rustc-codegen-cranelift"))
+                .map(|c| format!("{} ({release})", c.trim()))
+        });
+    let (cpu, memory) = cpu_and_memory();
+
+    Ok(Environment {
+        cargo: cmd!("cargo", "-V").dir(cargo_dir).read()?,
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        nightly_date,
+        mold: version("mold", &["--version"], cargo_dir),
+        cranelift,
+        hyperfine: version("hyperfine", &["--version"], cargo_dir),
+        cpu,
+        cores: std::thread::available_parallelism().map(|n| n.get()).ok(),
+        memory,
+        kernel: version("uname", &["-sr"], cargo_dir),
+        rustc,
+    })
+}
+
+impl Environment {
+    /// Every recorded property as a label and a value, in the order reports show them.
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = vec![
+            ("OS", format!("{} ({})", self.os, self.arch)),
+            ("Rustc", self.rustc.lines().next().unwrap_or("").to_string()),
+            ("Cargo", self.cargo.trim().to_string()),
+        ];
+        let optional = [
+            ("Nightly date", self.nightly_date.clone()),
+            ("Mold", self.mold.clone()),
+            ("Cranelift", self.cranelift.clone()),
+            ("Hyperfine", self.hyperfine.clone()),
+            ("CPU", self.cpu.clone()),
+            ("Cores", self.cores.map(|c| c.to_string())),
+            (
+                "Memory",
+                self.memory
+                    .map(|m| format!("{:.1} GiB", m as f64 / (1u64 << 30) as f64)),
+            ),
+            ("Kernel", self.kernel.clone()),
+        ];
+        for (label, value) in optional {
+            if let Some(value) = value {
+                properties.push((label, value));
+            }
+        }
+        properties
+    }
+
+    /// The properties that differ from `other`, as `label: ours vs theirs`.
+    pub fn differences(&self, other: &Environment) -> Vec<String> {
+        let theirs = other.properties();
+        let mut differences = Vec::new();
+        for (label, value) in self.properties() {
+            match theirs.iter().find(|(l, _)| *l == label) {
+                Some((_, other)) if *other == value => {}
+                Some((_, other)) => differences.push(format!("{label}: {value} vs {other}")),
+                None => differences.push(format!("{label}: {value} vs unknown")),
+            }
+        }
+        for (label, value) in &theirs {
+            if !self.properties().iter().any(|(l, _)| l == label) {
+                differences.push(format!("{label}: unknown vs {value}"));
+            }
+        }
+        differences
+    }
+}
+
+pub fn write(output_dir: &str, environment: &Environment) -> Result<(), Error> {
+    let path = Path::new(output_dir).join(ENVIRONMENT_FILE);
+    fs::write(path, serde_json::to_string_pretty(environment)?)
+}
+
+/// Sessions recorded before environments were captured have no file.
+pub fn load(output_dir: &str) -> Result<Option<Environment>, Error> {
+    let path = Path::new(output_dir).join(ENVIRONMENT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&contents)?))
+}
@@ -0,0 +1,800 @@
+use crate::matrix::Matrix;
+use crate::results::RunMetadata;
+use crate::runner::Runner;
+use crate::timings::{self, heaviest_packages};
+use crate::toggle::{
+    Cranelift, HotDependencies, Linker, OptLevelSweep, Parallel, ProfileSetting, Toggle, O3,
+};
+use crate::{
+    dev_opt_level, parse, profile_value, read_project_file, workspace_packages,
+    CargoCommandOptions, OptLevel, ProfileValue, CARGO_TOML, DEFAULT_THREADS,
+};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+/// The built-in toggles, as named in an experiment file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Setting {
+    Mold,
+    O3,
+    Cranelift,
+    Parallel,
+    Lld,
+    Gold,
+    Wild,
+    #[serde(rename = "rust-lld")]
+    RustLld,
+    #[serde(rename = "hot-deps")]
+    HotDeps,
+}
+
+impl Setting {
+    pub fn toggle(&self) -> Arc<dyn Toggle> {
+        match self {
+            Setting::Mold => Arc::new(Linker::MOLD),
+            Setting::O3 => Arc::new(O3),
+            Setting::Cranelift => Arc::new(Cranelift),
+            Setting::Parallel => Arc::new(Parallel::default()),
+            Setting::Lld => Arc::new(Linker::LLD),
+            Setting::Gold => Arc::new(Linker::GOLD),
+            Setting::Wild => Arc::new(Linker::WILD),
+            Setting::RustLld => Arc::new(Linker::RUST_LLD),
+            Setting::HotDeps => Arc::new(HotDependencies::new(Vec::new(), OptLevel::Level(3))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::Mold => "mold",
+            Setting::O3 => "o3",
+            Setting::Cranelift => "cranelift",
+            Setting::Parallel => "parallel",
+            Setting::Lld => "lld",
+            Setting::Gold => "gold",
+            Setting::Wild => "wild",
+            Setting::RustLld => "rust-lld",
+            Setting::HotDeps => "hot-deps",
+        }
+    }
+}
+
+/// A `-Z threads` count of a sweep, either a number or `"nproc"` for the number of cores.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ThreadCount {
+    Count(usize),
+    Named(NamedThreadCount),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamedThreadCount {
+    Nproc,
+}
+
+impl ThreadCount {
+    pub fn resolve(&self) -> usize {
+        match self {
+            ThreadCount::Count(n) => *n,
+            ThreadCount::Named(NamedThreadCount::Nproc) => std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+}
+
+fn default_parallel_threads() -> usize {
+    DEFAULT_THREADS
+}
+
+fn default_hot_opt_level() -> OptLevel {
+    OptLevel::Level(3)
+}
+
+/// Which dependencies the `hot-deps` toggle optimizes.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HotDependencySettings {
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// Also optimize the dependencies that took longest to compile in the timings of
+    /// `from_run`, which has to have been run with `timings = true`.
+    pub heaviest: Option<usize>,
+    /// The run `heaviest` picks from, by default the first scenario without any toggles.
+    pub from_run: Option<String>,
+    #[serde(default = "default_hot_opt_level")]
+    pub opt_level: OptLevel,
+}
+
+/// Debuginfo settings to sweep in a `Cargo.toml` profile. Each value becomes a toggle
+/// named after the setting, e.g. `debug-limited` or `deps-debug-0`, and the configuration
+/// with none of a setting's toggles enabled leaves it as the project has it.
+#[derive(Clone, PartialEq, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DebuginfoSettings {
+    /// The profile they're set in, `dev` by default.
+    pub profile: Option<String>,
+    /// Values of `debug`, e.g. `0`, `"line-tables-only"`, `"limited"` or `"full"`.
+    #[serde(default)]
+    pub debug: Vec<ProfileValue>,
+    /// Values of `debug` for every dependency, through `package."*"`.
+    #[serde(default)]
+    pub dependency_debug: Vec<ProfileValue>,
+    /// Values of `split-debuginfo`: `"off"`, `"packed"` or `"unpacked"`.
+    #[serde(default)]
+    pub split_debuginfo: Vec<ProfileValue>,
+    /// Values of `strip`: `"none"`, `"debuginfo"` or `"symbols"`.
+    #[serde(default)]
+    pub strip: Vec<ProfileValue>,
+}
+
+impl DebuginfoSettings {
+    pub fn profile(&self) -> &str {
+        self.profile.as_deref().unwrap_or("dev")
+    }
+
+    fn is_empty(&self) -> bool {
+        self.debug.is_empty()
+            && self.dependency_debug.is_empty()
+            && self.split_debuginfo.is_empty()
+            && self.strip.is_empty()
+    }
+
+    fn settings(&self) -> [(&'static str, &Vec<ProfileValue>, bool); 4] {
+        [
+            ("debug", &self.debug, false),
+            ("debug", &self.dependency_debug, true),
+            ("split-debuginfo", &self.split_debuginfo, false),
+            ("strip", &self.strip, false),
+        ]
+    }
+
+    /// The first value cargo wouldn't accept, and for which setting.
+    fn invalid(&self) -> Option<(&'static str, &ProfileValue)> {
+        self.settings()
+            .into_iter()
+            .find_map(|(key, values, _)| Some((key, values.iter().find(|v| !is_valid(key, v))?)))
+    }
+
+    /// One toggle per distinct value of every setting, other than the value `cargo_toml`
+    /// already has, if given. Disabling them goes back to that value.
+    fn toggles(&self, cargo_toml: Option<&str>) -> Result<Vec<Arc<dyn Toggle>>, Error> {
+        let profile = self.profile();
+        let mut toggles: Vec<Arc<dyn Toggle>> = Vec::new();
+        for (key, values, dependencies) in self.settings() {
+            let package = dependencies.then_some("*");
+            let original = match cargo_toml {
+                Some(cargo_toml) => profile_value(cargo_toml, profile, package, key)?,
+                None => None,
+            };
+            for (i, value) in values.iter().enumerate() {
+                let mut seen = values[..i].iter().chain(&original);
+                if seen.any(|v| v.is_equivalent(value, key)) {
+                    continue;
+                }
+                let toggle = match dependencies {
+                    true => ProfileSetting::dependencies(profile, key, value.clone()),
+                    false => ProfileSetting::new(profile, key, value.clone()),
+                };
+                toggles.push(Arc::new(toggle.restoring(original.clone())));
+            }
+        }
+        Ok(toggles)
+    }
+}
+
+/// The named values cargo accepts for a debuginfo setting.
+fn setting_names(key: &str) -> &'static [&'static str] {
+    match key {
+        "debug" => &[
+            "none",
+            "line-directives-only",
+            "line-tables-only",
+            "limited",
+            "full",
+        ],
+        "split-debuginfo" => &["off", "packed", "unpacked"],
+        _ => &["none", "debuginfo", "symbols"],
+    }
+}
+
+fn is_valid(key: &str, value: &ProfileValue) -> bool {
+    match (key, value) {
+        ("debug", ProfileValue::Integer(level)) => (0..=2).contains(level),
+        ("debug" | "strip", ProfileValue::Bool(_)) => true,
+        (_, ProfileValue::String(name)) => setting_names(key).contains(&name.as_str()),
+        _ => false,
+    }
+}
+
+/// A way of preparing the project before each timed build, e.g. `cargo clean`.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub prepare_command: String,
+    /// Overrides the experiment's runner for this scenario.
+    pub runner: Option<Runner>,
+}
+
+/// The contents of a `customs.toml` experiment file.
+#[derive(PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub cargo_command: String,
+    pub output_dir: String,
+    pub runs: u8,
+    #[serde(default)]
+    pub warmup_runs: u8,
+    #[serde(default)]
+    pub runner: Runner,
+    /// Collect `cargo --timings` reports for every build. The cargo command has
+    /// to accept `--timings`.
+    #[serde(default)]
+    pub timings: bool,
+    pub toggles: Vec<Setting>,
+    /// `-Z threads` of the `parallel` toggle.
+    #[serde(default = "default_parallel_threads")]
+    pub parallel_threads: usize,
+    /// Thread counts to sweep the parallel frontend over. Each becomes a `threads<N>`
+    /// toggle of its own, and the matrix runs them one at a time.
+    #[serde(default)]
+    pub threads: Vec<ThreadCount>,
+    /// `opt-level`s to sweep `profile.dev` over, each an `opt-<level>` toggle. The
+    /// project's own level is the one with none of them enabled, so it's always run.
+    #[serde(default)]
+    pub opt_levels: Vec<OptLevel>,
+    /// `opt-level`s to sweep the dependencies over through `profile.dev.package."*"`,
+    /// each a `deps-opt-<level>` toggle, independently of `opt_levels`.
+    #[serde(default)]
+    pub dependency_opt_levels: Vec<OptLevel>,
+    /// Required by the `hot-deps` toggle.
+    pub hot_dependencies: Option<HotDependencySettings>,
+    #[serde(default)]
+    pub debuginfo: DebuginfoSettings,
+    pub scenarios: Vec<Scenario>,
+    /// Explicit list of enabled toggles per configuration. When absent, every
+    /// combination of `toggles` allowed by `include`/`exclude` is run.
+    pub configurations: Option<Vec<Vec<Setting>>>,
+    /// Rules of toggle names, which can be those of sweeps like `opt-3` or `threads4`.
+    #[serde(default)]
+    pub include: Vec<Vec<String>>,
+    #[serde(default)]
+    pub exclude: Vec<Vec<String>>,
+    /// The project's `Cargo.toml` before any toggle changed it, see [`Experiment::read_project`].
+    #[serde(skip)]
+    manifest: Option<String>,
+}
+
+/// Which of a set of toggles are on, in the order they were declared.
+#[derive(Clone)]
+pub struct ToggleState(Vec<(Arc<dyn Toggle>, bool)>);
+
+impl PartialEq for ToggleState {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|((a, a_on), (b, b_on))| a.name() == b.name() && a_on == b_on)
+    }
+}
+
+impl fmt::Debug for ToggleState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(t, on)| (t.name(), on)))
+            .finish()
+    }
+}
+
+impl ToggleState {
+    pub fn all_disabled(toggles: &[Arc<dyn Toggle>]) -> Self {
+        ToggleState(toggles.iter().map(|t| (t.clone(), false)).collect())
+    }
+
+    pub fn from_enabled(toggles: &[Arc<dyn Toggle>], enabled: &[&str]) -> Self {
+        ToggleState(
+            toggles
+                .iter()
+                .map(|t| (t.clone(), enabled.contains(&t.name())))
+                .collect(),
+        )
+    }
+
+    /// Reads which of `toggles` are currently switched on in the project.
+    pub fn current(toggles: &[Arc<dyn Toggle>], cargo_dir: &str) -> Result<Self, Error> {
+        let mut state = Vec::new();
+        for toggle in toggles {
+            state.push((toggle.clone(), toggle.is_enabled(cargo_dir)?));
+        }
+        Ok(ToggleState(state))
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.0.iter().any(|(t, on)| t.name() == name && *on)
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &dyn Toggle> {
+        self.0.iter().filter(|(_, on)| *on).map(|(t, _)| t.as_ref())
+    }
+
+    /// Two enabled toggles of the same [`Toggle::group`], if there are any.
+    pub fn conflict(&self) -> Option<(&str, &str)> {
+        let enabled: Vec<&dyn Toggle> = self.enabled().collect();
+        enabled.iter().enumerate().find_map(|(i, a)| {
+            let b = enabled[i + 1..]
+                .iter()
+                .find(|b| a.group().is_some() && a.group() == b.group())?;
+            Some((a.name(), b.name()))
+        })
+    }
+
+    /// The toggles that differ from `from`, and whether they need to be turned on.
+    /// Toggles are turned off first, so alternatives of a group never overlap.
+    pub fn changes_from<'a>(
+        &'a self,
+        from: &'a ToggleState,
+    ) -> impl Iterator<Item = (&'a dyn Toggle, bool)> + 'a {
+        let changed = |on: bool| {
+            self.0
+                .iter()
+                .filter(move |(t, enabled)| *enabled == on && from.is_enabled(t.name()) != on)
+                .map(|(t, on)| (t.as_ref(), *on))
+        };
+        changed(false).chain(changed(true))
+    }
+
+    /// How many toggles have to be flipped to get from `from` to this state.
+    pub fn flips_from(&self, from: &ToggleState) -> usize {
+        self.changes_from(from).count()
+    }
+
+    /// Enables or disables only the toggles that differ from `from`.
+    pub fn transition(&self, from: &ToggleState, cargo_dir: &str) -> Result<(), Error> {
+        for (toggle, on) in self.changes_from(from) {
+            if on {
+                toggle.enable(cargo_dir)?;
+            } else {
+                toggle.disable(cargo_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn metadata(&self, scenario: &str) -> RunMetadata {
+        RunMetadata {
+            scenario: scenario.to_string(),
+            toggles: self
+                .0
+                .iter()
+                .map(|(t, on)| (t.name().to_string(), *on))
+                .collect(),
+        }
+    }
+
+    pub fn run_name(&self, scenario: &str) -> String {
+        let mut name = scenario.to_string();
+        for toggle in self.enabled() {
+            name.push('_');
+            name.push_str(toggle.name());
+        }
+        name
+    }
+}
+
+/// A single hyperfine invocation and the toggles that must be active for it.
+#[derive(PartialEq, Debug)]
+pub struct Step {
+    pub scenario: String,
+    pub toggles: ToggleState,
+    pub options: CargoCommandOptions,
+}
+
+impl Experiment {
+    pub fn load(path: &str) -> Result<Experiment, Error> {
+        let contents = fs::read_to_string(path)?;
+        let experiment: Experiment = toml::from_str(&contents)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{path}: {e}")))?;
+        experiment.validate()?;
+        Ok(experiment)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.configurations.is_some() && !(self.include.is_empty() && self.exclude.is_empty()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "`include`/`exclude` only apply to generated matrices, not to `configurations`",
+            ));
+        }
+        let sweeps = !(self.threads.is_empty()
+            && self.opt_levels.is_empty()
+            && self.dependency_opt_levels.is_empty()
+            && self.debuginfo.is_empty());
+        if self.configurations.is_some() && sweeps {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "`threads`, `opt_levels`, `dependency_opt_levels` and [debuginfo] only apply to generated matrices, not to `configurations`",
+            ));
+        }
+        if let Some((key, value)) = self.debuginfo.invalid() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{value} isn't a valid {key}, use one of {}",
+                    setting_names(key).join(", ")
+                ),
+            ));
+        }
+        let opt_levels = self.opt_levels.iter().chain(&self.dependency_opt_levels);
+        if let Some(level) = opt_levels.clone().find(|l| !l.is_valid()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("opt-level {level} doesn't exist, use 0-3, \"s\" or \"z\""),
+            ));
+        }
+        if opt_levels.count() > 0 && self.toggles.contains(&Setting::O3) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "`o3` sets the opt-levels that are being swept, leave it out of `toggles`",
+            ));
+        }
+        if self.parallel_threads == 0 || self.threads.contains(&ThreadCount::Count(0)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "thread counts have to be at least 1",
+            ));
+        }
+        if self.toggles.contains(&Setting::Parallel)
+            && self.thread_counts().contains(&self.parallel_threads)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "`threads` sweeps {}, which `parallel` already runs with, leave it out of one of them",
+                    self.parallel_threads
+                ),
+            ));
+        }
+        match &self.hot_dependencies {
+            None if self.toggles.contains(&Setting::HotDeps) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "`hot-deps` needs a [hot_dependencies] section with `packages` or `heaviest`",
+                ))
+            }
+            Some(hot) if hot.packages.is_empty() && hot.heaviest.is_none() => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "[hot_dependencies] needs `packages` or `heaviest`",
+                ))
+            }
+            Some(hot) if !hot.opt_level.is_valid() => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "opt-level {} doesn't exist, use 0-3, \"s\" or \"z\"",
+                        hot.opt_level
+                    ),
+                ))
+            }
+            _ => {}
+        }
+        // Configurations can't be combined with sweeps, so the toggles they name are all listed
+        let toggles = self.listed_toggles(None)?;
+        for enabled in self.configurations.iter().flatten() {
+            let enabled: Vec<&str> = enabled.iter().map(Setting::name).collect();
+            if let Some((a, b)) = ToggleState::from_enabled(&toggles, &enabled).conflict() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("`{a}` and `{b}` are alternatives and can't be enabled together"),
+                ));
+            }
+        }
+        for set in self.configurations.iter().flatten() {
+            if let Some(setting) = set.iter().find(|s| !self.toggles.contains(s)) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("`{}` is not listed in `toggles`", setting.name()),
+                ));
+            }
+        }
+        let names = self.toggle_names()?;
+        for rule in self.include.iter().chain(&self.exclude) {
+            if let Some(name) = rule.iter().find(|n| !names.contains(n)) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "`{name}` is neither listed in `toggles` nor one of the sweeps: {}",
+                        names.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The distinct counts of the `threads` sweep.
+    fn thread_counts(&self) -> Vec<usize> {
+        let mut counts: Vec<usize> = self.threads.iter().map(ThreadCount::resolve).collect();
+        counts.sort();
+        counts.dedup();
+        counts
+    }
+
+    /// The names of every toggle listed or swept, including values the project may
+    /// already have.
+    fn toggle_names(&self) -> Result<Vec<String>, Error> {
+        let mut toggles = self.listed_toggles(None)?;
+        for threads in self.thread_counts() {
+            toggles.push(Arc::new(Parallel::sweep(threads)));
+        }
+        for level in &self.opt_levels {
+            toggles.push(Arc::new(OptLevelSweep::profile(*level)));
+        }
+        for level in &self.dependency_opt_levels {
+            toggles.push(Arc::new(OptLevelSweep::dependencies(*level)));
+        }
+        toggles.extend(self.debuginfo.toggles(None)?);
+        Ok(toggles.iter().map(|t| t.name().to_string()).collect())
+    }
+
+    /// The toggles listed in `toggles`. `hot-deps` goes back to the levels `cargo_toml`
+    /// has for its packages, and leaves out those already at its level.
+    fn listed_toggles(&self, cargo_toml: Option<&str>) -> Result<Vec<Arc<dyn Toggle>>, Error> {
+        let mut toggles: Vec<Arc<dyn Toggle>> = Vec::new();
+        for setting in &self.toggles {
+            toggles.push(match (setting, &self.hot_dependencies) {
+                (Setting::Parallel, _) => Arc::new(Parallel::new(self.parallel_threads)),
+                (Setting::HotDeps, Some(hot)) => {
+                    let mut packages = Vec::new();
+                    let mut original = Vec::new();
+                    for package in &hot.packages {
+                        let level = match cargo_toml {
+                            Some(cargo_toml) => dev_opt_level(cargo_toml, Some(package))?,
+                            None => None,
+                        };
+                        if level != Some(hot.opt_level) {
+                            packages.push(package.clone());
+                            original.push(level);
+                        }
+                    }
+                    Arc::new(HotDependencies::new(packages, hot.opt_level).restoring(original))
+                }
+                _ => setting.toggle(),
+            });
+        }
+        Ok(toggles)
+    }
+
+    /// The toggles of `toggles`, followed by one per value of each sweep. Sweeps and
+    /// `hot-deps` go back to the project's own settings, so they need
+    /// [`Experiment::read_project`] first.
+    pub fn resolve_toggles(&self) -> Result<Vec<Arc<dyn Toggle>>, Error> {
+        let sweeps = !(self.opt_levels.is_empty()
+            && self.dependency_opt_levels.is_empty()
+            && self.debuginfo.is_empty());
+        let manifest = match sweeps || self.toggles.contains(&Setting::HotDeps) {
+            true => Some(self.manifest()?),
+            false => None,
+        };
+        let mut toggles = self.listed_toggles(manifest)?;
+        for threads in self.thread_counts() {
+            toggles.push(Arc::new(Parallel::sweep(threads)));
+        }
+        let Some(manifest) = manifest.filter(|_| sweeps) else {
+            return Ok(toggles);
+        };
+
+        // The level the project has is what the configurations without a sweep's toggles
+        // build with. Dependencies without a level of their own follow the profile's, so
+        // every level is a change for them.
+        let original = dev_opt_level(manifest, None)?;
+        let dependency_original = dev_opt_level(manifest, Some("*"))?;
+        let levels = |levels: &[OptLevel], current: Option<OptLevel>| {
+            let mut unique: Vec<OptLevel> = Vec::new();
+            for level in levels {
+                if Some(*level) != current && !unique.contains(level) {
+                    unique.push(*level);
+                }
+            }
+            unique
+        };
+        for level in levels(&self.opt_levels, original.or(Some(OptLevel::Level(0)))) {
+            toggles.push(Arc::new(OptLevelSweep::profile(level).restoring(original)));
+        }
+        for level in levels(&self.dependency_opt_levels, dependency_original) {
+            toggles.push(Arc::new(
+                OptLevelSweep::dependencies(level).restoring(dependency_original),
+            ));
+        }
+        toggles.extend(self.debuginfo.toggles(Some(manifest))?);
+        Ok(toggles)
+    }
+
+    /// Reads the project's `Cargo.toml`, whose settings the sweeps go back to when
+    /// they're disabled. Has to happen before any toggle changes the project.
+    pub fn read_project(&mut self, cargo_dir: &str) -> Result<(), Error> {
+        let manifest = read_project_file(cargo_dir, CARGO_TOML)?;
+        parse(&manifest)?;
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    fn manifest(&self) -> Result<&str, Error> {
+        self.manifest.as_deref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "the project's Cargo.toml has to be read with `read_project` first, to restore its settings",
+            )
+        })
+    }
+
+    /// The run whose timings the `heaviest` dependencies are picked from, if there are any to pick.
+    pub fn hot_dependencies_run(&self) -> Option<String> {
+        let hot = self
+            .hot_dependencies
+            .as_ref()
+            .filter(|h| h.heaviest.is_some())?;
+        hot.from_run
+            .clone()
+            .or_else(|| self.scenarios.first().map(|s| s.name.clone()))
+    }
+
+    /// Adds the `heaviest` dependencies of `[hot_dependencies]` to its `packages`, from
+    /// the timings of an earlier session in `output_dir`. Returns what was added.
+    pub fn pick_hot_dependencies(&mut self, cargo_dir: &str) -> Result<Vec<String>, Error> {
+        let Some(run) = self.hot_dependencies_run() else {
+            return Ok(Vec::new());
+        };
+        let Some(hot) = &mut self.hot_dependencies else {
+            return Ok(Vec::new());
+        };
+        let heaviest = hot.heaviest.unwrap_or_default();
+        let units = timings::load(&format!("{}/{run}.timings", self.output_dir)).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("no timings for {run} to pick the heaviest dependencies from, run it with `timings = true` first ({e})"),
+            )
+        })?;
+        let mut exclude = workspace_packages(cargo_dir)?;
+        exclude.extend(hot.packages.iter().cloned());
+        let picked = heaviest_packages(&units, &exclude, heaviest);
+        hot.packages.extend(picked.iter().cloned());
+        Ok(picked)
+    }
+
+    pub fn toggle_states(&self) -> Result<Vec<ToggleState>, Error> {
+        let toggles = self.resolve_toggles()?;
+        Ok(match &self.configurations {
+            Some(configurations) => configurations
+                .iter()
+                .map(|enabled| {
+                    let enabled: Vec<&str> = enabled.iter().map(Setting::name).collect();
+                    ToggleState::from_enabled(&toggles, &enabled)
+                })
+                .collect(),
+            None => Matrix::builder()
+                .toggles(toggles)
+                .include(self.include.clone())
+                .exclude(self.exclude.clone())
+                .build()
+                .states(),
+        })
+    }
+
+    /// Expands every configuration into one step per scenario.
+    pub fn plan(&self, compile_path: &str) -> Result<Vec<Step>, Error> {
+        let mut steps = Vec::new();
+        for toggles in self.toggle_states()? {
+            for scenario in &self.scenarios {
+                let options = CargoCommandOptions::builder()
+                    .prepare_command(scenario.prepare_command.clone())
+                    .runs(self.runs)
+                    .warmup_runs(self.warmup_runs)
+                    .cargo_command(self.cargo_command.clone())
+                    .output_dir(self.output_dir.clone())
+                    .run_name(toggles.run_name(&scenario.name))
+                    .compile_path(compile_path.to_string())
+                    .runner(scenario.runner.unwrap_or(self.runner))
+                    .timings(self.timings)
+                    .build();
+                steps.push(Step {
+                    scenario: scenario.name.clone(),
+                    toggles: toggles.clone(),
+                    options,
+                });
+            }
+        }
+        Ok(steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An experiment with `settings` added to the keys every experiment needs,
+    /// validated the way [`Experiment::load`] does.
+    fn experiment(settings: &str) -> Result<Experiment, Error> {
+        let contents = format!(
+            "cargo_command = \"cargo build\"\noutput_dir = \"results\"\nruns = 3\n{settings}\n\n\
+             [[scenarios]]\nname = \"clean\"\nprepare_command = \"cargo clean\"\n"
+        );
+        let experiment: Experiment = toml::from_str(&contents).unwrap();
+        experiment.validate()?;
+        Ok(experiment)
+    }
+
+    fn names(toggles: &[Arc<dyn Toggle>]) -> Vec<&str> {
+        toggles.iter().map(|t| t.name()).collect()
+    }
+
+    #[test]
+    fn sweeps_need_the_projects_manifest() {
+        let experiment = experiment("toggles = []\nopt_levels = [1]").unwrap();
+        assert!(experiment.resolve_toggles().is_err());
+    }
+
+    #[test]
+    fn dependency_levels_are_swept_independently_of_the_profiles() {
+        let mut experiment =
+            experiment("toggles = []\nopt_levels = [0, 1]\ndependency_opt_levels = [0, 3]")
+                .unwrap();
+        experiment.manifest = Some("[profile.dev]\nopt-level = 1\n".to_string());
+        let toggles = experiment.resolve_toggles().unwrap();
+        // Without a `package."*"` level of their own, dependencies follow the profile's
+        assert_eq!(names(&toggles), ["opt-0", "deps-opt-0", "deps-opt-3"]);
+        assert_eq!(experiment.toggle_states().unwrap().len(), 2 * 3);
+    }
+
+    #[test]
+    fn hot_dependencies_go_back_to_the_projects_levels() {
+        let mut experiment = experiment(
+            "toggles = [\"hot-deps\"]\n\n[hot_dependencies]\npackages = [\"sqlx\", \"image\"]",
+        )
+        .unwrap();
+        let manifest = "[profile.dev.package.sqlx]\nopt-level = 3\n\n[profile.dev.package.image]\nopt-level = 1\n";
+        experiment.manifest = Some(manifest.to_string());
+        let toggles = experiment.resolve_toggles().unwrap();
+        let on = toggles[0].plan(true, manifest).unwrap();
+        assert!(toggles[0].is_enabled_in(&on).unwrap());
+        assert_eq!(toggles[0].plan(false, &on).unwrap(), manifest);
+    }
+
+    #[test]
+    fn rules_can_name_sweep_toggles() {
+        let mut experiment = experiment(
+            "toggles = [\"mold\"]\nopt_levels = [3]\ndependency_opt_levels = [0]\nexclude = [[\"opt-3\", \"deps-opt-0\"]]",
+        )
+        .unwrap();
+        experiment.manifest = Some(String::new());
+        let states = experiment.toggle_states().unwrap();
+        assert_eq!(states.len(), 2 * 2 * 2 - 2);
+        assert!(states
+            .iter()
+            .all(|s| !(s.is_enabled("opt-3") && s.is_enabled("deps-opt-0"))));
+    }
+
+    #[test]
+    fn rules_reject_unknown_toggles() {
+        assert!(experiment("toggles = [\"mold\"]\ninclude = [[\"opt-3\"]]").is_err());
+        assert!(experiment("toggles = [\"mold\"]\nexclude = [[\"mold\", \"lld\"]]").is_err());
+    }
+
+    #[test]
+    fn threads_cant_repeat_the_parallel_count() {
+        assert!(
+            experiment("toggles = [\"parallel\"]\nparallel_threads = 4\nthreads = [2, 4]").is_err()
+        );
+        let experiment =
+            experiment("toggles = [\"parallel\"]\nparallel_threads = 8\nthreads = [4, 2, 4]")
+                .unwrap();
+        let toggles = experiment.resolve_toggles().unwrap();
+        assert_eq!(names(&toggles), ["parallel", "threads2", "threads4"]);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::results::Run;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Error;
+
+/// A single timed iteration of a run.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct TimingRow {
+    pub run_name: String,
+    pub scenario: String,
+    #[serde(flatten)]
+    pub toggles: BTreeMap<String, bool>,
+    pub iteration: usize,
+    pub wall_time: f64,
+    /// hyperfine only exports the mean user and system time of a run, so unless
+    /// the native runner measured the run, these repeat that mean for every iteration.
+    pub user_time: f64,
+    pub system_time: f64,
+    pub exit_code: Option<i32>,
+}
+
+/// Flattens every run into one row per iteration. Every row carries every toggle
+/// seen in any run, so the columns line up.
+pub fn timing_rows(runs: &[Run]) -> Vec<TimingRow> {
+    let toggles: BTreeSet<&String> = runs
+        .iter()
+        .flat_map(|r| r.metadata.toggles.keys())
+        .collect();
+
+    let mut rows = Vec::new();
+    for run in runs {
+        let states: BTreeMap<String, bool> = toggles
+            .iter()
+            .map(|t| ((*t).clone(), run.metadata.toggles.get(*t) == Some(&true)))
+            .collect();
+        for (iteration, time) in run.result.times.iter().enumerate() {
+            rows.push(TimingRow {
+                run_name: run.run_name.clone(),
+                scenario: run.metadata.scenario.clone(),
+                toggles: states.clone(),
+                iteration,
+                wall_time: *time,
+                user_time: run
+                    .result
+                    .user_times
+                    .get(iteration)
+                    .copied()
+                    .unwrap_or(run.result.user),
+                system_time: run
+                    .result
+                    .system_times
+                    .get(iteration)
+                    .copied()
+                    .unwrap_or(run.result.system),
+                exit_code: run.result.exit_codes.get(iteration).copied().flatten(),
+            });
+        }
+    }
+    rows
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn to_csv(rows: &[TimingRow]) -> String {
+    let toggles: Vec<&String> = rows
+        .first()
+        .map(|r| r.toggles.keys().collect())
+        .unwrap_or_default();
+
+    let mut header = vec!["run_name".to_string(), "scenario".to_string()];
+    header.extend(toggles.iter().map(|t| csv_field(t)));
+    header.extend(
+        [
+            "iteration",
+            "wall_time",
+            "user_time",
+            "system_time",
+            "exit_code",
+        ]
+        .map(String::from),
+    );
+    let mut out = header.join(",");
+    out.push('\n');
+
+    for row in rows {
+        let mut fields = vec![csv_field(&row.run_name), csv_field(&row.scenario)];
+        fields.extend(toggles.iter().map(|t| row.toggles[*t].to_string()));
+        fields.push(row.iteration.to_string());
+        fields.push(row.wall_time.to_string());
+        fields.push(row.user_time.to_string());
+        fields.push(row.system_time.to_string());
+        fields.push(row.exit_code.map_or(String::new(), |c| c.to_string()));
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn to_jsonl(rows: &[TimingRow]) -> Result<String, Error> {
+    let mut out = String::new();
+    for row in rows {
+        out.push_str(&serde_json::to_string(row)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
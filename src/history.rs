@@ -0,0 +1,191 @@
+use crate::environment::{Environment, ENVIRONMENT_FILE};
+use crate::results::{load_runs, Run};
+use duct::cmd;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory in `output_dir` that every session is copied into, one directory each.
+pub const HISTORY_DIR: &str = "history";
+/// Written into each session directory of the history.
+pub const SESSION_FILE: &str = "session.json";
+
+/// What a session was measured against.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// `HEAD` of the project, if it is a git repository.
+    pub commit: Option<String>,
+    /// Whether the project had uncommitted changes.
+    pub dirty: bool,
+    /// SHA-256 of the project's `Cargo.lock`.
+    pub cargo_lock: Option<String>,
+    /// First line of `rustc -vV`, e.g. `rustc 1.76.0-nightly (...)`.
+    pub toolchain: String,
+}
+
+impl Session {
+    /// Looks at the project before customs touches it, so `dirty` only reflects the user's changes.
+    pub fn capture(cargo_dir: &str, environment: &Environment) -> Result<Session, Error> {
+        let git = |args: &[&str]| cmd("git", args).dir(cargo_dir).stderr_null().read().ok();
+        let commit = git(&["rev-parse", "HEAD"]);
+        let dirty = commit.is_some()
+            && git(&["status", "--porcelain"]).is_some_and(|s| !s.trim().is_empty());
+
+        Ok(Session {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(Error::other)?
+                .as_secs(),
+            commit,
+            dirty,
+            cargo_lock: cargo_lock_hash(cargo_dir),
+            toolchain: environment.rustc.lines().next().unwrap_or("").to_string(),
+        })
+    }
+
+    /// The commit abbreviated like git does, with `-dirty` if there were local changes.
+    pub fn short_commit(&self) -> String {
+        match &self.commit {
+            Some(commit) if self.dirty => format!("{}-dirty", &commit[..commit.len().min(9)]),
+            Some(commit) => commit[..commit.len().min(9)].to_string(),
+            None => "-".to_string(),
+        }
+    }
+
+    /// e.g. `2023-11-05 14:03:27 UTC`
+    pub fn date(&self) -> String {
+        let (year, month, day, seconds) = civil(self.timestamp);
+        format!(
+            "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+
+    /// Name of the session's directory, which sorts chronologically.
+    fn dir_name(&self) -> String {
+        let (year, month, day, seconds) = civil(self.timestamp);
+        format!(
+            "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Splits a Unix timestamp into a UTC date and the seconds into that day.
+fn civil(timestamp: u64) -> (i64, u64, u64, u64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u64;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u64;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day, timestamp % 86400)
+}
+
+/// The lock file sits next to the workspace's manifest, which may be above `cargo_dir`.
+fn cargo_lock_hash(cargo_dir: &str) -> Option<String> {
+    let manifest = cmd!(
+        "cargo",
+        "locate-project",
+        "--workspace",
+        "--message-format",
+        "plain"
+    )
+    .dir(cargo_dir)
+    .stderr_null()
+    .read()
+    .ok()?;
+    let lock = fs::read(Path::new(&manifest).with_file_name("Cargo.lock")).ok()?;
+    Some(
+        Sha256::digest(lock)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect(),
+    )
+}
+
+/// Copies a session's results and environment into a new directory of the history.
+/// Existing sessions are never touched.
+pub fn record(
+    output_dir: &str,
+    session: &Session,
+    result_paths: &[String],
+) -> Result<String, Error> {
+    let dir = Path::new(output_dir)
+        .join(HISTORY_DIR)
+        .join(session.dir_name());
+    if dir.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} is already in the history", dir.to_string_lossy()),
+        ));
+    }
+    fs::create_dir_all(&dir)?;
+    for path in result_paths {
+        let path = Path::new(path);
+        if let Some(name) = path.file_name() {
+            fs::copy(path, dir.join(name))?;
+        }
+    }
+    let environment = Path::new(output_dir).join(ENVIRONMENT_FILE);
+    if environment.exists() {
+        fs::copy(environment, dir.join(ENVIRONMENT_FILE))?;
+    }
+    fs::write(
+        dir.join(SESSION_FILE),
+        serde_json::to_string_pretty(session)?,
+    )?;
+    Ok(dir.to_string_lossy().to_string())
+}
+
+/// Every recorded session with its directory, oldest first.
+pub fn sessions(output_dir: &str) -> Result<Vec<(String, Session)>, Error> {
+    let history = Path::new(output_dir).join(HISTORY_DIR);
+    if !history.exists() {
+        return Ok(Vec::new());
+    }
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(history)? {
+        let dir = entry?.path();
+        let path = dir.join(SESSION_FILE);
+        if !path.exists() {
+            continue;
+        }
+        let session: Session = serde_json::from_str(&fs::read_to_string(path)?)?;
+        sessions.push((dir.to_string_lossy().to_string(), session));
+    }
+    sessions.sort_by(|a, b| {
+        a.1.timestamp
+            .cmp(&b.1.timestamp)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    Ok(sessions)
+}
+
+/// `run_name`'s results in every session that ran it with the session's directory, oldest first.
+pub fn history(output_dir: &str, run_name: &str) -> Result<Vec<(String, Session, Run)>, Error> {
+    let mut entries = Vec::new();
+    for (dir, session) in sessions(output_dir)? {
+        if let Some(run) = load_runs(&dir)?
+            .into_iter()
+            .find(|r| r.run_name == run_name)
+        {
+            entries.push((dir, session, run));
+        }
+    }
+    Ok(entries)
+}
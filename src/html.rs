@@ -0,0 +1,283 @@
+use crate::environment::Environment;
+use crate::report::{threads_label, ScalingCurve, ScenarioSummary};
+use crate::results::Run;
+use std::collections::BTreeSet;
+
+const WIDTH: f64 = 900.0;
+const LABEL_WIDTH: f64 = 320.0;
+const ROW_HEIGHT: f64 = 26.0;
+const AXIS_HEIGHT: f64 = 30.0;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin:1em 0}\
+td,th{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+td:first-child,th:first-child{text-align:left}\
+svg text{font-size:12px;font-family:monospace}\
+pre{background:#f4f4f4;padding:1em}";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Linear interpolation between the closest ranks of an already sorted slice.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Maps seconds onto the horizontal plotting area.
+struct Scale {
+    max: f64,
+}
+
+impl Scale {
+    fn x(&self, seconds: f64) -> f64 {
+        LABEL_WIDTH + seconds / self.max * (WIDTH - LABEL_WIDTH - 20.0)
+    }
+
+    fn axis(&self, y: f64) -> String {
+        let mut svg = format!(
+            r##"<line x1="{x0:.1}" y1="{y:.1}" x2="{x1:.1}" y2="{y:.1}" stroke="#888"/>"##,
+            x0 = self.x(0.0),
+            x1 = self.x(self.max),
+        );
+        for tick in 0..=4 {
+            let seconds = self.max * tick as f64 / 4.0;
+            svg.push_str(&format!(
+                r##"<text x="{x:.1}" y="{ty:.1}" text-anchor="middle">{seconds:.1}s</text>"##,
+                x = self.x(seconds),
+                ty = y + 16.0,
+            ));
+        }
+        svg
+    }
+}
+
+fn svg_open(rows: usize) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{:.0}">"#,
+        rows as f64 * ROW_HEIGHT + AXIS_HEIGHT
+    )
+}
+
+fn label(run: &Run, y: f64) -> String {
+    format!(
+        r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+        LABEL_WIDTH - 8.0,
+        y + 4.0,
+        escape(&run.run_name)
+    )
+}
+
+/// Mean as a bar with a ±stddev error bar, one row per run.
+fn bar_chart(summary: &ScenarioSummary) -> String {
+    let max = summary
+        .rows
+        .iter()
+        .map(|r| r.run.result.mean + r.run.result.stddev.unwrap_or(0.0))
+        .fold(0.0, f64::max);
+    let scale = Scale { max };
+
+    let mut svg = svg_open(summary.rows.len());
+    for (i, row) in summary.rows.iter().enumerate() {
+        let r = &row.run.result;
+        let y = i as f64 * ROW_HEIGHT + ROW_HEIGHT / 2.0;
+        let fill = if row.run.metadata.is_default() {
+            "#999"
+        } else {
+            "#4a7bd0"
+        };
+        svg.push_str(&label(&row.run, y));
+        svg.push_str(&format!(
+            r#"<rect x="{x:.1}" y="{top:.1}" width="{w:.1}" height="{h:.1}" fill="{fill}"><title>{title}</title></rect>"#,
+            x = scale.x(0.0),
+            top = y - ROW_HEIGHT * 0.35,
+            w = scale.x(r.mean) - scale.x(0.0),
+            h = ROW_HEIGHT * 0.7,
+            title = escape(&format!("{}: {:.3}s", row.run.run_name, r.mean)),
+        ));
+        if let Some(stddev) = r.stddev {
+            let (low, high) = (scale.x(r.mean - stddev), scale.x(r.mean + stddev));
+            svg.push_str(&format!(
+                r##"<path d="M{low:.1} {y0:.1}V{y1:.1}M{low:.1} {y:.1}H{high:.1}M{high:.1} {y0:.1}V{y1:.1}" stroke="#222" fill="none"/>"##,
+                y0 = y - 5.0,
+                y1 = y + 5.0,
+            ));
+        }
+    }
+    svg.push_str(&scale.axis(summary.rows.len() as f64 * ROW_HEIGHT));
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Min/quartiles/max of the individual timings, one row per run.
+fn box_plot(summary: &ScenarioSummary) -> String {
+    let max = summary
+        .rows
+        .iter()
+        .map(|r| r.run.result.max)
+        .fold(0.0, f64::max);
+    let scale = Scale { max };
+
+    let mut svg = svg_open(summary.rows.len());
+    for (i, row) in summary.rows.iter().enumerate() {
+        let r = &row.run.result;
+        let y = i as f64 * ROW_HEIGHT + ROW_HEIGHT / 2.0;
+        let mut times = r.times.clone();
+        times.sort_by(f64::total_cmp);
+        let (q1, q3) = if times.is_empty() {
+            (r.median, r.median)
+        } else {
+            (quantile(&times, 0.25), quantile(&times, 0.75))
+        };
+        let (min, median, max) = (scale.x(r.min), scale.x(r.median), scale.x(r.max));
+        svg.push_str(&label(&row.run, y));
+        svg.push_str(&format!(
+            r##"<path d="M{min:.1} {y:.1}H{q1x:.1}M{q3x:.1} {y:.1}H{max:.1}M{min:.1} {y0:.1}V{y1:.1}M{max:.1} {y0:.1}V{y1:.1}" stroke="#222" fill="none"/>"##,
+            q1x = scale.x(q1),
+            q3x = scale.x(q3),
+            y0 = y - 5.0,
+            y1 = y + 5.0,
+        ));
+        svg.push_str(&format!(
+            r##"<rect x="{x:.1}" y="{top:.1}" width="{w:.1}" height="{h:.1}" fill="#cfdcf3" stroke="#222"/>"##,
+            x = scale.x(q1),
+            top = y - ROW_HEIGHT * 0.35,
+            w = scale.x(q3) - scale.x(q1),
+            h = ROW_HEIGHT * 0.7,
+        ));
+        svg.push_str(&format!(
+            r##"<path d="M{median:.1} {y0:.1}V{y1:.1}" stroke="#c03" stroke-width="2"/>"##,
+            y0 = y - ROW_HEIGHT * 0.35,
+            y1 = y + ROW_HEIGHT * 0.35,
+        ));
+    }
+    svg.push_str(&scale.axis(summary.rows.len() as f64 * ROW_HEIGHT));
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Which toggles each run had enabled.
+fn legend(summary: &ScenarioSummary) -> String {
+    let toggles: BTreeSet<&String> = summary
+        .rows
+        .iter()
+        .flat_map(|r| r.run.metadata.toggles.keys())
+        .collect();
+
+    let mut html = String::from("<table><tr><th>Run</th>");
+    for toggle in &toggles {
+        html.push_str(&format!("<th>{}</th>", escape(toggle)));
+    }
+    html.push_str(&format!(
+        "<th>Mean</th><th>Speedup</th><th>{:.0}% CI</th><th>p (Welch / MWU)</th></tr>",
+        (1.0 - summary.alpha) * 100.0
+    ));
+    for row in &summary.rows {
+        html.push_str(&format!("<tr><td>{}</td>", escape(&row.run.run_name)));
+        for toggle in &toggles {
+            let on = row.run.metadata.toggles.get(*toggle).copied() == Some(true);
+            html.push_str(if on { "<td>&#9679;</td>" } else { "<td></td>" });
+        }
+        html.push_str(&format!(
+            "<td>{:.3}s</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            row.run.result.mean,
+            row.speedup_label(),
+            row.ci_label(),
+            row.p_label(),
+        ));
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Mean time per thread count as a line, with the thread counts evenly spaced.
+fn scaling_chart(curve: &ScalingCurve) -> String {
+    const HEIGHT: f64 = 240.0;
+    const MARGIN: f64 = 50.0;
+    let max = curve.points.iter().map(|p| p.mean).fold(0.0, f64::max);
+    let step = (WIDTH - 2.0 * MARGIN) / (curve.points.len().max(2) - 1) as f64;
+    let x = |i: usize| MARGIN + i as f64 * step;
+    let y = |mean: f64| MARGIN / 2.0 + (1.0 - mean / max) * (HEIGHT - MARGIN * 1.5);
+
+    let mut svg =
+        format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}">"#);
+    let path: Vec<String> = curve
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("{:.1},{:.1}", x(i), y(p.mean)))
+        .collect();
+    svg.push_str(&format!(
+        r##"<polyline points="{}" stroke="#4a7bd0" stroke-width="2" fill="none"/>"##,
+        path.join(" ")
+    ));
+    for (i, point) in curve.points.iter().enumerate() {
+        svg.push_str(&format!(
+            r##"<circle cx="{cx:.1}" cy="{cy:.1}" r="4" fill="#4a7bd0"><title>{title}</title></circle><text x="{cx:.1}" y="{ty:.1}" text-anchor="middle">{mean:.2}s</text><text x="{cx:.1}" y="{ly:.1}" text-anchor="middle">{threads}</text>"##,
+            cx = x(i),
+            cy = y(point.mean),
+            ty = y(point.mean) - 10.0,
+            ly = HEIGHT - 8.0,
+            mean = point.mean,
+            threads = threads_label(point),
+            title = escape(&point.run_name),
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Renders a session as a single HTML page with inline SVG and CSS, so it can
+/// be opened offline or attached to a ticket.
+pub fn html_report(
+    summaries: &[ScenarioSummary],
+    scaling: &[ScalingCurve],
+    environment: Option<&Environment>,
+) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Customs benchmark report</title><style>{STYLE}</style></head><body>\n<h1>Customs benchmark report</h1>\n"
+    );
+    for summary in summaries {
+        html.push_str(&format!("<h2>{}</h2>\n", escape(&summary.scenario)));
+        html.push_str("<h3>Mean and standard deviation</h3>\n");
+        html.push_str(&bar_chart(summary));
+        html.push_str("\n<h3>Distribution of individual runs</h3>\n");
+        html.push_str(&box_plot(summary));
+        html.push_str("\n<h3>Toggles</h3>\n");
+        html.push_str(&legend(summary));
+        html.push('\n');
+    }
+    if !scaling.is_empty() {
+        html.push_str("<h2>Thread scaling</h2>\n");
+    }
+    for curve in scaling {
+        html.push_str(&format!("<h3>{}</h3>\n", escape(&curve.label())));
+        html.push_str(&scaling_chart(curve));
+        html.push('\n');
+    }
+    html.push_str("<h2>Environment</h2>\n");
+    match environment {
+        Some(env) => {
+            html.push_str("<table>");
+            for (label, value) in env.properties() {
+                html.push_str(&format!(
+                    "<tr><th>{label}</th><td>{}</td></tr>",
+                    escape(&value)
+                ));
+            }
+            html.push_str(&format!(
+                "</table><pre>{}</pre>\n",
+                escape(env.rustc.trim())
+            ));
+        }
+        None => html.push_str("<p>Not recorded for this session.</p>\n"),
+    }
+    html.push_str("</body></html>\n");
+    html
+}
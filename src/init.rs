@@ -0,0 +1,93 @@
+use crate::plan::Change;
+use crate::{parse, read_project_file, table, CARGO_TOML, CONFIG_TOML};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use toml_edit::{value, Item, Table};
+
+/// Sets `key` only if it isn't there yet, so existing choices are kept.
+fn default(table: &mut Table, key: &str, item: Item) {
+    if !table.contains_key(key) {
+        table.insert(key, item);
+    }
+}
+
+/// Adds the profiles customs needs to a `Cargo.toml`: `opt-level`s for
+/// `profile.dev` and its dependencies, and a `server-dev` profile inheriting from it.
+pub fn plan_cargo_toml(cargo_toml: &str) -> Result<String, Error> {
+    let mut toml = parse(cargo_toml)?;
+    let profile = table(toml.as_table_mut(), "profile")?;
+
+    let dev = table(profile, "dev")?;
+    dev.set_implicit(false);
+    default(dev, "opt-level", value(0));
+    let deps = table(table(dev, "package")?, "*")?;
+    deps.set_implicit(false);
+    default(deps, "opt-level", value(0));
+
+    let server_dev = table(profile, "server-dev")?;
+    server_dev.set_implicit(false);
+    default(server_dev, "inherits", value("dev"));
+
+    Ok(toml.to_string())
+}
+
+/// Enables the unstable `codegen-backend` option and adds the `server-dev`
+/// profile the cranelift toggle sets its backend in.
+pub fn plan_config_toml(config_toml: &str) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
+
+    let unstable = table(toml.as_table_mut(), "unstable")?;
+    unstable.set_implicit(false);
+    if unstable.get("codegen-backend").and_then(Item::as_bool) != Some(true) {
+        unstable.insert("codegen-backend", value(true));
+    }
+
+    let server_dev = table(table(toml.as_table_mut(), "profile")?, "server-dev")?;
+    server_dev.set_implicit(false);
+
+    Ok(toml.to_string())
+}
+
+/// What `init` would change in the project, one entry per file that isn't set up yet.
+pub fn plan(cargo_dir: &str) -> Result<Vec<Change>, Error> {
+    if !Path::new(cargo_dir).join(CARGO_TOML).exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{cargo_dir} has no {CARGO_TOML}"),
+        ));
+    }
+    let mut changes = Vec::new();
+    for (file, plan) in [
+        (
+            CARGO_TOML,
+            plan_cargo_toml as fn(&str) -> Result<String, Error>,
+        ),
+        (CONFIG_TOML, plan_config_toml),
+    ] {
+        let before = read_project_file(cargo_dir, file)?;
+        let after = plan(&before)?;
+        if after != before {
+            changes.push(Change {
+                file: file.to_string(),
+                before,
+                after,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Sets the project up for customs, leaving everything that's already there alone.
+/// Running it again changes nothing.
+pub fn init(cargo_dir: &str) -> Result<Vec<Change>, Error> {
+    let changes = plan(cargo_dir)?;
+    for change in &changes {
+        let path = Path::new(cargo_dir).join(&change.file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &change.after)?;
+    }
+    Ok(changes)
+}
@@ -1,7 +1,31 @@
-use duct::cmd;
+pub mod doctor;
+pub mod environment;
+pub mod experiment;
+pub mod export;
+pub mod history;
+pub mod html;
+pub mod init;
+pub mod markdown;
+pub mod matrix;
+pub mod plan;
+pub mod regression;
+pub mod report;
+pub mod results;
+pub mod runner;
+pub mod rustflags;
+pub mod snapshot;
+pub mod stats;
+pub mod timings;
+pub mod toggle;
+
+use runner::{run_native, Runner};
+use rustflags::{plan_add_flags, plan_remove_flags, Location};
+use serde::Deserialize;
+use std::fmt;
 use std::fs;
-use std::io::{prelude::*, Error};
-use toml_edit::{value, Array, Document};
+use std::io::{Error, ErrorKind};
+use std::sync::OnceLock;
+use toml_edit::{value, Document, Item, Table};
 use typed_builder::TypedBuilder;
 
 #[derive(PartialEq, Debug, TypedBuilder)]
@@ -14,242 +38,639 @@ pub struct CargoCommandOptions {
     output_dir: String,
     run_name: String,
     compile_path: String,
+    #[builder(default)]
+    runner: Runner,
+    /// Append `--timings` to the cargo command and keep cargo's reports of every build
+    #[builder(default)]
+    timings: bool,
+}
+
+impl CargoCommandOptions {
+    pub fn run_name(&self) -> &str {
+        &self.run_name
+    }
+
+    /// Where hyperfine exports the results of this run.
+    pub fn result_path(&self) -> String {
+        format!("{}/{}.json", &self.output_dir, &self.run_name)
+    }
+
+    /// Where the cargo timing reports of this run are collected.
+    pub fn timings_dir(&self) -> String {
+        format!("{}/{}.timings", &self.output_dir, &self.run_name)
+    }
+
+    /// The prepare command, preceded by collecting the last build's timing report.
+    pub fn effective_prepare_command(&self) -> String {
+        match self.timings {
+            true => format!(
+                "{}; {}",
+                timings::collect_command(&self.timings_dir()),
+                self.prepare_command
+            ),
+            false => self.prepare_command.clone(),
+        }
+    }
+
+    pub fn effective_cargo_command(&self) -> String {
+        match self.timings {
+            true => format!("{} --timings", self.cargo_command),
+            false => self.cargo_command.clone(),
+        }
+    }
+}
+
+/// The hyperfine arguments `inspect` runs with.
+pub fn hyperfine_args(opts: &CargoCommandOptions) -> Vec<String> {
+    vec![
+        "-p".to_string(),
+        opts.effective_prepare_command(),
+        "--warmup".to_string(),
+        opts.warmup_runs.to_string(),
+        opts.effective_cargo_command(),
+        "--export-json".to_string(),
+        opts.result_path(),
+        format!("--runs={}", &opts.runs),
+    ]
+}
+
+/// The exact command `inspect` runs, quoted so it can be pasted into a shell.
+/// The native runner takes the same arguments, but isn't a separate program.
+pub fn command_line(opts: &CargoCommandOptions) -> String {
+    let mut line = match opts.runner {
+        Runner::Hyperfine => String::from("hyperfine"),
+        Runner::Native => String::from("(native runner)"),
+    };
+    for arg in hyperfine_args(opts) {
+        line.push(' ');
+        if arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=./:".contains(c))
+        {
+            line.push_str(&arg);
+        } else {
+            line.push_str(&format!("'{}'", arg.replace('\'', r"'\''")));
+        }
+    }
+    line
 }
 
 pub fn inspect(opts: &CargoCommandOptions) -> Result<(), Error> {
-    let runs = format!("--runs={}", &opts.runs);
     std::env::set_current_dir(&opts.compile_path).unwrap();
 
-    cmd!("pwd").run()?;
-    cmd!(
-        "hyperfine",
-        "-p",
-        &opts.prepare_command,
-        "--warmup",
-        &opts.warmup_runs.to_string(),
-        &opts.cargo_command,
-        "--export-json",
-        format!("{}/{}.json", &opts.output_dir, &opts.run_name),
-        &runs
-    )
-    .run()?;
+    duct::cmd!("pwd").run()?;
+    if opts.timings {
+        timings::start(&opts.timings_dir())?;
+    }
+    match opts.runner {
+        Runner::Hyperfine => {
+            duct::cmd("hyperfine", hyperfine_args(opts)).run()?;
+        }
+        Runner::Native => run_native(opts)?,
+    }
+    if opts.timings {
+        // The last build has no prepare command after it to collect its report
+        duct::cmd!("sh", "-c", timings::collect_command(&opts.timings_dir())).run()?;
+        timings::aggregate(&opts.timings_dir(), opts.warmup_runs.into())?;
+    }
     Ok(())
 }
-pub fn enable_o3(cargo_dir: &str) -> Result<(), Error> {
-    let mut cargo_toml = fs::File::open(format!("{cargo_dir}/Cargo.toml"))?;
-    let mut cargo_toml_string = String::new();
-    cargo_toml.read_to_string(&mut cargo_toml_string)?;
 
-    let mut toml = cargo_toml_string.parse::<Document>().unwrap();
+pub const CARGO_TOML: &str = "Cargo.toml";
+pub const CONFIG_TOML: &str = ".cargo/config.toml";
 
-    toml["profile"]["dev"]["opt-level"] = value(1);
-    toml["profile"]["dev"]["package"]["*"]["opt-level"] = value(3);
+pub(crate) fn parse(contents: &str) -> Result<Document, Error> {
+    contents
+        .parse::<Document>()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// The table at `key` in `parent`, created empty if it's missing. Tables that only
+/// exist to hold others don't get a header of their own.
+pub(crate) fn table<'a>(parent: &'a mut Table, key: &str) -> Result<&'a mut Table, Error> {
+    parent
+        .entry(key)
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("`{key}` has to be a table like [{key}], not an inline table or value"),
+            )
+        })
+}
 
-    let new_toml_string = toml.to_string();
+/// The contents of a file in `cargo_dir`, empty if it doesn't exist yet. Toggles
+/// create `.cargo/config.toml` when they first write to it.
+pub(crate) fn read_project_file(cargo_dir: &str, file: &str) -> Result<String, Error> {
+    match fs::read_to_string(format!("{cargo_dir}/{file}")) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        contents => contents,
+    }
+}
 
-    let mut cargo_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/Cargo.toml"))?;
-    cargo_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
+/// Applies one of the `plan_*` functions to a file in `cargo_dir`, only writing
+/// it if the contents change.
+pub(crate) fn rewrite(
+    cargo_dir: &str,
+    file: &str,
+    plan: impl FnOnce(&str) -> Result<String, Error>,
+) -> Result<(), Error> {
+    let path = std::path::Path::new(cargo_dir).join(file);
+    let contents = read_project_file(cargo_dir, file)?;
+    let new_contents = plan(&contents)?;
+    if new_contents != contents {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, new_contents)?;
+    }
     Ok(())
 }
 
-pub fn disable_o3(cargo_dir: &str) -> Result<(), Error> {
-    let mut cargo_toml = fs::File::open(format!("{cargo_dir}/Cargo.toml"))?;
-    let mut cargo_toml_string = String::new();
-    cargo_toml.read_to_string(&mut cargo_toml_string)?;
+pub fn plan_enable_o3(cargo_toml: &str) -> Result<String, Error> {
+    let mut toml = parse(cargo_toml)?;
+
+    toml["profile"]["dev"]["opt-level"] = value(1);
+    toml["profile"]["dev"]["package"]["*"]["opt-level"] = value(3);
 
-    let mut toml = cargo_toml_string.parse::<Document>().unwrap();
+    Ok(toml.to_string())
+}
+pub fn enable_o3(cargo_dir: &str) -> Result<(), Error> {
+    rewrite(cargo_dir, CARGO_TOML, plan_enable_o3)
+}
+
+pub fn plan_disable_o3(cargo_toml: &str) -> Result<String, Error> {
+    let mut toml = parse(cargo_toml)?;
 
     toml["profile"]["dev"]["opt-level"] = value(0);
     toml["profile"]["dev"]["package"]["*"]["opt-level"] = value(0);
 
-    let new_toml_string = toml.to_string();
+    Ok(toml.to_string())
+}
+pub fn disable_o3(cargo_dir: &str) -> Result<(), Error> {
+    rewrite(cargo_dir, CARGO_TOML, plan_disable_o3)
+}
+
+/// An `opt-level` cargo accepts in a profile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OptLevel {
+    Level(u8),
+    Named(NamedOptLevel),
+}
 
-    let mut cargo_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/Cargo.toml"))?;
-    cargo_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
-    Ok(())
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamedOptLevel {
+    /// Optimize for size.
+    S,
+    /// Optimize for size, without loop vectorization.
+    Z,
 }
-pub fn enable_cranelift(cargo_dir: &str) -> Result<(), Error> {
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
 
-    let mut toml = config_toml_string.parse::<Document>().unwrap();
-    let codegen = &mut toml["profile"]["server-dev"];
-    if let toml_edit::Item::Table(t) = codegen {
-        if t.contains_key("codegen-backend") {
-            return Ok(());
-        } else {
-            t["codegen-backend"] = value("cranelift");
+impl OptLevel {
+    fn from_item(item: &Item) -> Option<Self> {
+        match (item.as_integer(), item.as_str()) {
+            (Some(level), _) => u8::try_from(level).ok().map(OptLevel::Level),
+            (_, Some("s")) => Some(OptLevel::Named(NamedOptLevel::S)),
+            (_, Some("z")) => Some(OptLevel::Named(NamedOptLevel::Z)),
+            _ => None,
+        }
+    }
+
+    fn to_item(self) -> Item {
+        match self {
+            OptLevel::Level(level) => value(i64::from(level)),
+            OptLevel::Named(NamedOptLevel::S) => value("s"),
+            OptLevel::Named(NamedOptLevel::Z) => value("z"),
         }
     }
 
-    let new_toml_string = toml.to_string();
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, OptLevel::Level(level) if *level > 3)
+    }
+}
 
-    let mut config_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    config_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptLevel::Level(level) => write!(f, "{level}"),
+            OptLevel::Named(NamedOptLevel::S) => write!(f, "s"),
+            OptLevel::Named(NamedOptLevel::Z) => write!(f, "z"),
+        }
+    }
+}
 
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-    Ok(())
+/// The `profile.<profile>` table, or its `package.<name>` table overriding it for a
+/// package, `"*"` being every dependency.
+fn profile_table<'a>(
+    toml: &'a mut Document,
+    profile: &str,
+    package: Option<&str>,
+) -> Result<&'a mut Table, Error> {
+    let profile = table(table(toml.as_table_mut(), "profile")?, profile)?;
+    match package {
+        Some(package) => table(table(profile, "package")?, package),
+        None => Ok(profile),
+    }
+}
+
+/// A setting of `profile.<profile>`, or of one of its package overrides.
+fn profile_setting(
+    cargo_toml: &str,
+    profile: &str,
+    package: Option<&str>,
+    key: &str,
+) -> Result<Option<Item>, Error> {
+    let mut toml = parse(cargo_toml)?;
+    Ok(profile_table(&mut toml, profile, package)?
+        .get(key)
+        .cloned())
+}
+
+/// Sets `key` in `profile.<profile>`, or in one of its package overrides, leaving
+/// everything else alone.
+fn plan_set_profile_setting(
+    cargo_toml: &str,
+    profile: &str,
+    package: Option<&str>,
+    key: &str,
+    mut item: Item,
+) -> Result<String, Error> {
+    let mut toml = parse(cargo_toml)?;
+    let table = profile_table(&mut toml, profile, package)?;
+    table.set_implicit(false);
+    // Assign in place, so the comments around the old line stay where they were
+    match table.get_mut(key) {
+        Some(old) => {
+            if let (Some(old), Some(new)) = (old.as_value(), item.as_value_mut()) {
+                *new.decor_mut() = old.decor().clone();
+            }
+            *old = item;
+        }
+        None => {
+            table.insert(key, item);
+        }
+    }
+    Ok(toml.to_string())
+}
+
+/// Takes `key` out of `profile.<profile>`, or out of one of its package overrides,
+/// and the override itself once it's empty.
+pub fn plan_remove_profile_setting(
+    cargo_toml: &str,
+    profile: &str,
+    package: Option<&str>,
+    key: &str,
+) -> Result<String, Error> {
+    let mut toml = parse(cargo_toml)?;
+    let profile = toml
+        .get_mut("profile")
+        .and_then(|p| p.get_mut(profile))
+        .and_then(Item::as_table_like_mut);
+    let Some(profile) = profile else {
+        return Ok(cargo_toml.to_string());
+    };
+    let Some(package) = package else {
+        return match profile.remove(key) {
+            Some(_) => Ok(toml.to_string()),
+            None => Ok(cargo_toml.to_string()),
+        };
+    };
+    let Some(packages) = profile.get_mut("package").and_then(Item::as_table_like_mut) else {
+        return Ok(cargo_toml.to_string());
+    };
+    let Some(overrides) = packages.get_mut(package).and_then(Item::as_table_like_mut) else {
+        return Ok(cargo_toml.to_string());
+    };
+    if overrides.remove(key).is_none() {
+        return Ok(cargo_toml.to_string());
+    }
+    if overrides.is_empty() {
+        packages.remove(package);
+    }
+    Ok(toml.to_string())
+}
+
+/// The `opt-level` of `profile.dev`, or of one of its package overrides.
+pub fn dev_opt_level(cargo_toml: &str, package: Option<&str>) -> Result<Option<OptLevel>, Error> {
+    let level = profile_setting(cargo_toml, "dev", package, "opt-level")?;
+    Ok(level.as_ref().and_then(OptLevel::from_item))
+}
+
+/// Sets the `opt-level` of `profile.dev`, or of one of its package overrides,
+/// leaving everything else alone.
+pub fn plan_set_dev_opt_level(
+    cargo_toml: &str,
+    package: Option<&str>,
+    level: OptLevel,
+) -> Result<String, Error> {
+    if dev_opt_level(cargo_toml, package)? == Some(level) {
+        return Ok(cargo_toml.to_string());
+    }
+    plan_set_profile_setting(cargo_toml, "dev", package, "opt-level", level.to_item())
+}
+
+/// A profile setting as written in an experiment file, e.g. `debug = "limited"`.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ProfileValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl ProfileValue {
+    fn from_item(item: &Item) -> Option<Self> {
+        match item.as_value()? {
+            toml_edit::Value::Boolean(b) => Some(ProfileValue::Bool(*b.value())),
+            toml_edit::Value::Integer(i) => Some(ProfileValue::Integer(*i.value())),
+            toml_edit::Value::String(s) => Some(ProfileValue::String(s.value().clone())),
+            _ => None,
+        }
+    }
+
+    fn to_item(&self) -> Item {
+        match self {
+            ProfileValue::Bool(b) => value(*b),
+            ProfileValue::Integer(i) => value(*i),
+            ProfileValue::String(s) => value(s.as_str()),
+        }
+    }
+
+    /// The name cargo gives the value of `key`, for the values it has several spellings of.
+    fn canonical(&self, key: &str) -> Option<&str> {
+        match (key, self) {
+            ("debug", ProfileValue::Bool(false) | ProfileValue::Integer(0)) => Some("none"),
+            ("debug", ProfileValue::Integer(1)) => Some("limited"),
+            ("debug", ProfileValue::Bool(true) | ProfileValue::Integer(2)) => Some("full"),
+            ("strip", ProfileValue::Bool(false)) => Some("none"),
+            ("strip", ProfileValue::Bool(true)) => Some("symbols"),
+            (_, ProfileValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Whether cargo reads both as the same value of `key`, like `debug = 1` and
+    /// `debug = "limited"`.
+    pub fn is_equivalent(&self, other: &ProfileValue, key: &str) -> bool {
+        self == other || matches!(self.canonical(key), Some(c) if other.canonical(key) == Some(c))
+    }
+}
+
+impl fmt::Display for ProfileValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileValue::Bool(b) => write!(f, "{b}"),
+            ProfileValue::Integer(i) => write!(f, "{i}"),
+            ProfileValue::String(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// The value of `key` in `profile.<profile>`, or in one of its package overrides.
+pub fn profile_value(
+    cargo_toml: &str,
+    profile: &str,
+    package: Option<&str>,
+    key: &str,
+) -> Result<Option<ProfileValue>, Error> {
+    let item = profile_setting(cargo_toml, profile, package, key)?;
+    Ok(item.as_ref().and_then(ProfileValue::from_item))
 }
-pub fn disable_cranelift(cargo_dir: &str) -> Result<(), Error> {
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
 
-    let mut toml = config_toml_string.parse::<Document>().unwrap();
+/// Sets `key` to `value` in `profile.<profile>`, or in one of its package overrides.
+pub fn plan_set_profile_value(
+    cargo_toml: &str,
+    profile: &str,
+    package: Option<&str>,
+    key: &str,
+    value: &ProfileValue,
+) -> Result<String, Error> {
+    if profile_value(cargo_toml, profile, package, key)?
+        .is_some_and(|v| v.is_equivalent(value, key))
+    {
+        return Ok(cargo_toml.to_string());
+    }
+    plan_set_profile_setting(cargo_toml, profile, package, key, value.to_item())
+}
+
+/// The names of the workspace's own packages.
+pub fn workspace_packages(cargo_dir: &str) -> Result<Vec<String>, Error> {
+    let metadata = duct::cmd!("cargo", "metadata", "--no-deps", "--format-version", "1")
+        .dir(cargo_dir)
+        .stderr_null()
+        .read()?;
+    let metadata: serde_json::Value = serde_json::from_str(&metadata)?;
+    Ok(metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|p| p["name"].as_str().map(String::from))
+        .collect())
+}
+
+pub fn plan_enable_cranelift(config_toml: &str) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
     let codegen = &mut toml["profile"]["server-dev"];
     if let toml_edit::Item::Table(t) = codegen {
-        if t.contains_key("codegen-backend") {
-            t.remove("codegen-backend");
-        } else {
-            return Ok(());
+        if !t.contains_key("codegen-backend") {
+            t["codegen-backend"] = value("cranelift");
         }
     }
+    Ok(toml.to_string())
+}
+pub fn enable_cranelift(cargo_dir: &str) -> Result<(), Error> {
+    rewrite(cargo_dir, CONFIG_TOML, plan_enable_cranelift)
+}
 
-    let new_toml_string = toml.to_string();
+pub fn plan_disable_cranelift(config_toml: &str) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
+    let codegen = &mut toml["profile"]["server-dev"];
+    if let toml_edit::Item::Table(t) = codegen {
+        t.remove("codegen-backend");
+    }
+    Ok(toml.to_string())
+}
+pub fn disable_cranelift(cargo_dir: &str) -> Result<(), Error> {
+    rewrite(cargo_dir, CONFIG_TOML, plan_disable_cranelift)
+}
 
-    let mut config_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    config_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
+/// The `-Z threads` the parallel frontend runs with unless told otherwise.
+pub const DEFAULT_THREADS: usize = 8;
 
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-    Ok(())
+pub fn plan_enable_parallel(config_toml: &str, threads: usize) -> Result<String, Error> {
+    let threads = format!("threads={threads}");
+    plan_add_flags(config_toml, &Location::Target(None), &["-Z", &threads])
+}
+pub fn enable_parallel(cargo_dir: &str, threads: usize) -> Result<(), Error> {
+    rewrite(cargo_dir, CONFIG_TOML, |c| plan_enable_parallel(c, threads))
 }
 
-pub fn enable_parallel(cargo_dir: &str) -> Result<(), Error> {
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-
-    let mut toml = config_toml_string.parse::<Document>().unwrap();
-    let build = &mut toml["build"];
+pub fn plan_disable_parallel(config_toml: &str, threads: usize) -> Result<String, Error> {
+    let threads = format!("threads={threads}");
+    plan_remove_flags(config_toml, &Location::Target(None), &["-Z", &threads])
+}
+pub fn disable_parallel(cargo_dir: &str, threads: usize) -> Result<(), Error> {
+    rewrite(cargo_dir, CONFIG_TOML, |c| {
+        plan_disable_parallel(c, threads)
+    })
+}
 
-    if let toml_edit::Item::Table(t) = build {
-        if t.contains_key("rustflags") {
-            return Ok(());
-        } else {
-            let mut flags = Array::new();
-            flags.push("-Z");
-            flags.push("threads=8");
-            t["rustflags"] = value(flags);
-        }
-    } else {
-        let mut flags = Array::new();
-        flags.push("-Z");
-        flags.push("threads=8");
-        toml["build"]["rustflags"] = value(flags);
-    }
-
-    let new_toml_string = toml.to_string();
-
-    let mut config_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    config_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
-
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-    Ok(())
+/// The triple cargo builds for with the config, `build.target` or else the host.
+pub(crate) fn target_triple(config: &Document) -> Result<String, Error> {
+    match config
+        .get("build")
+        .and_then(|b| b.get("target"))
+        .and_then(Item::as_str)
+    {
+        Some(triple) => Ok(triple.to_string()),
+        None => host_triple(),
+    }
 }
-pub fn disable_parallel(cargo_dir: &str) -> Result<(), Error> {
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
 
-    let mut toml = config_toml_string.parse::<Document>().unwrap();
-    let rust_flags = &mut toml["build"];
-    if let toml_edit::Item::Table(t) = rust_flags {
-        if t.contains_key("rustflags") {
-            t.remove("rustflags");
-        } else {
-            return Ok(());
+/// Trails a `linker` customs added, so disabling takes out only its own.
+const LINKER_MARK: &str = " # set by customs";
+
+/// Sets the linker driver of the target cargo builds for, `target.<triple>.linker`.
+/// A different linker the project already sets is an error rather than overwritten.
+pub fn plan_set_linker(config_toml: &str, linker: &str) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
+    let triple = target_triple(&toml)?;
+    let target = table(table(toml.as_table_mut(), "target")?, &triple)?;
+    match target.get("linker").map(|l| l.as_str()) {
+        Some(Some(existing)) if existing == linker => return Ok(config_toml.to_string()),
+        Some(existing) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "needs `linker = \"{linker}\"` under [target.{triple}], which sets it to {}",
+                    existing.map_or("something else".to_string(), |l| format!("\"{l}\""))
+                ),
+            ))
         }
+        None => {}
+    }
+    let mut driver = toml_edit::Value::from(linker);
+    driver.decor_mut().set_suffix(LINKER_MARK);
+    target.set_implicit(false);
+    target.insert("linker", Item::Value(driver));
+    Ok(toml.to_string())
+}
+
+/// Removes a `target.<triple>.linker` that [`plan_set_linker`] added. One the
+/// project sets itself is left alone.
+pub fn plan_unset_linker(config_toml: &str, linker: &str) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
+    let triple = target_triple(&toml)?;
+    let Some(target) = toml
+        .get_mut("target")
+        .and_then(|t| t.get_mut(&triple))
+        .and_then(Item::as_table_like_mut)
+    else {
+        return Ok(config_toml.to_string());
+    };
+    let ours = target
+        .get("linker")
+        .and_then(Item::as_value)
+        .is_some_and(|l| {
+            l.as_str() == Some(linker)
+                && l.decor().suffix().and_then(|s| s.as_str()) == Some(LINKER_MARK)
+        });
+    if !ours {
+        return Ok(config_toml.to_string());
     }
+    target.remove("linker");
+    Ok(toml.to_string())
+}
 
-    let new_toml_string = toml.to_string();
+/// The linker driver `target.<triple>.linker` sets for the target cargo builds for.
+pub fn linker(config_toml: &str) -> Result<Option<String>, Error> {
+    let toml = parse(config_toml)?;
+    let triple = target_triple(&toml)?;
+    Ok(toml
+        .get("target")
+        .and_then(|t| t.get(&triple))
+        .and_then(|t| t.get("linker"))
+        .and_then(Item::as_str)
+        .map(String::from))
+}
 
-    let mut config_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    config_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
+/// The flags that make the linker driver link with mold.
+pub const MOLD_FLAGS: [&str; 2] = ["-C", "link-arg=-fuse-ld=mold"];
 
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-    Ok(())
+/// The target triple rustc builds for by default, e.g. `x86_64-unknown-linux-gnu`.
+pub fn host_triple() -> Result<String, Error> {
+    static HOST: OnceLock<String> = OnceLock::new();
+    if let Some(host) = HOST.get() {
+        return Ok(host.clone());
+    }
+    let rustc = duct::cmd!("rustc", "-vV").read()?;
+    let host = rustc
+        .lines()
+        .find_map(|l| l.strip_prefix("host:"))
+        .map(|h| h.trim().to_string())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "`rustc -vV` printed no host"))?;
+    Ok(HOST.get_or_init(|| host).clone())
 }
 
+pub fn plan_enable_mold(config_toml: &str) -> Result<String, Error> {
+    plan_add_flags(config_toml, &Location::Target(None), &MOLD_FLAGS)
+}
 pub fn enable_mold(cargo_dir: &str) -> Result<(), Error> {
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-
-    let new_toml_string = config_toml_string
-        .replace("#linker", "linker")
-        .replace("#rustflags = [\"-C", "rustflags = [\"-C");
-
-    let mut config_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    config_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
-
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-    Ok(())
+    rewrite(cargo_dir, CONFIG_TOML, plan_enable_mold)
+}
+
+pub fn plan_disable_mold(config_toml: &str) -> Result<String, Error> {
+    plan_remove_flags(config_toml, &Location::Target(None), &MOLD_FLAGS)
 }
 pub fn disable_mold(cargo_dir: &str) -> Result<(), Error> {
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-
-    let new_toml_string = config_toml_string
-        .replace("linker", "#linker")
-        .replace("rustflags = [\"-C", "#rustflags = [\"-C");
-
-    let mut config_toml = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    config_toml
-        .write_all(new_toml_string.as_bytes())
-        .expect("Failed Write");
-
-    let mut config_toml = fs::File::open(format!("{cargo_dir}/.cargo/config.toml"))?;
-    let mut config_toml_string = String::new();
-    config_toml.read_to_string(&mut config_toml_string)?;
-    Ok(())
+    rewrite(cargo_dir, CONFIG_TOML, plan_disable_mold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_a_profile_value_keeps_the_comments_around_it() {
+        let cargo_toml = "[profile.dev]\n# keep\ndebug = 1 # limited\nopt-level = 0\n";
+        let off = ProfileValue::Integer(0);
+        let set = plan_set_profile_value(cargo_toml, "dev", None, "debug", &off).unwrap();
+        assert_eq!(
+            set,
+            "[profile.dev]\n# keep\ndebug = 0 # limited\nopt-level = 0\n"
+        );
+        let restored =
+            plan_set_profile_value(&set, "dev", None, "debug", &ProfileValue::Integer(1)).unwrap();
+        assert_eq!(restored, cargo_toml);
+    }
+
+    #[test]
+    fn equivalent_profile_values() {
+        let limited = ProfileValue::String("limited".to_string());
+        assert!(ProfileValue::Integer(1).is_equivalent(&limited, "debug"));
+        assert!(ProfileValue::Bool(true).is_equivalent(&ProfileValue::Integer(2), "debug"));
+        assert!(ProfileValue::Bool(true)
+            .is_equivalent(&ProfileValue::String("symbols".to_string()), "strip"));
+        assert!(!ProfileValue::Integer(1).is_equivalent(&ProfileValue::Bool(true), "debug"));
+        assert!(!ProfileValue::Bool(true).is_equivalent(&limited, "strip"));
+    }
+
+    #[test]
+    fn removing_the_last_override_setting_removes_the_override() {
+        let cargo_toml = "[profile.dev]\nopt-level = 1\n";
+        let set = plan_set_dev_opt_level(cargo_toml, Some("sqlx"), OptLevel::Level(3)).unwrap();
+        assert_eq!(
+            dev_opt_level(&set, Some("sqlx")).unwrap(),
+            Some(OptLevel::Level(3))
+        );
+        let removed = plan_remove_profile_setting(&set, "dev", Some("sqlx"), "opt-level").unwrap();
+        assert_eq!(removed, cargo_toml);
+    }
 }
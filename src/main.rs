@@ -1,378 +1,568 @@
-use clap::Parser;
-use customs::{
-    disable_cranelift, disable_mold, disable_o3, enable_cranelift, enable_mold, enable_o3,
-    enable_parallel, inspect, CargoCommandOptions,
-};
-use std::io::Error;
+use clap::{Parser, Subcommand, ValueEnum};
+use customs::environment::{self, Environment};
+use customs::experiment::{Experiment, ToggleState};
+use customs::export::{timing_rows, to_csv, to_jsonl};
+use customs::history::{self, Session};
+use customs::html::html_report;
+use customs::init;
+use customs::markdown::markdown_report;
+use customs::plan::DryRun;
+use customs::regression::{compare_runs, Change};
+use customs::report::{format_scaling, format_table, summarize, thread_scaling, DEFAULT_ALPHA};
+use customs::results::{annotate, load_runs, Run};
+use customs::snapshot::{self, Snapshot};
+use customs::stats::min_runs;
+use customs::timings::{self, UnitDiff, UnitTiming};
+use customs::{command_line, inspect};
+use std::fs;
+use std::io::{Error, ErrorKind};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
-    cargo_dir: String,
-    #[arg(short, long)]
-    output_dir: String,
-    #[arg(short, long, default_value_t = 10)]
-    runs: u8,
-    #[arg(short, long, default_value_t = 0)]
-    warmup_runs: u8,
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn main() -> Result<(), Error> {
-    println!("Running customs check on cargo!");
-    let args = Args::parse();
-    let sed_command =
-        r#"sed -i -e "s|<dfn>[^<]*</dfn>|<dfn>$(date +%m%s)</dfn>|g" app/src/routes/index.rs"#;
-    //1. Assume a clean state for compilation tests
-    //2. Do clean comcargo_dirompile run with hyperfine (Clean)
-    println!("Default Options");
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //4. Enable Mold (Mold)
-    println!("Mold Enabled");
-    enable_mold(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //6. Enable O3 (Mold and O3)
-    enable_o3(&args.cargo_dir)?;
-    println!("O3 and Mold Enabled");
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_mold_o3".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .warmup_runs(args.warmup_runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_mold_o3".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //8. Enable Cranelift (Mold, O3, and Cranelift)
-    enable_cranelift(&args.cargo_dir)?;
-    println!("O3, Mold, And Cranelift Enabled");
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_mold_o3_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_mold_o3_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //10. Disable Mold
-    disable_mold(&args.cargo_dir)?;
-    println!("Cranelift and O3 Enabled");
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_o3_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_o3_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //12. Disable O3 and Enable Mold (Mold and Cranelift)
-    println!("Cranelift and Mold Enabled");
-    disable_o3(&args.cargo_dir)?;
-    enable_mold(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_mold_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_mold_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //12. Disable Mold(Cranelift)
-    println!("Cranelift Enabled");
-    disable_mold(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-
-    //12. Disable Cranelift(O3)
-    println!("O3 Enabled");
-    disable_cranelift(&args.cargo_dir)?;
-    enable_o3(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_o3".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_o3".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
-    disable_o3(&args.cargo_dir)?;
-
-    // Enable Parallel Compilation
-    println!("Parallel Enabled");
-    enable_parallel(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
-
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run every configuration of the experiment under hyperfine
+    Run {
+        #[arg(short, long)]
+        cargo_dir: String,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+        /// Print the config diffs and hyperfine commands of every step instead of running them
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        compare: CompareArgs,
+    },
+    /// Summarize the hyperfine results of a previous run
+    Report {
+        /// Defaults to the output_dir of the experiment file
+        #[arg(short, long)]
+        output_dir: Option<String>,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+        #[arg(short, long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+        #[command(flatten)]
+        compare: CompareArgs,
+    },
+    /// Show the slowest units of a run recorded with `timings = true`
+    Timings {
+        run_name: String,
+        /// Defaults to the output_dir of the experiment file
+        #[arg(short, long)]
+        output_dir: Option<String>,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+        #[arg(short, long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Compare the per-unit compile times of two runs recorded with `timings = true`
+    Diff {
+        run_a: String,
+        run_b: String,
+        /// Defaults to the output_dir of the experiment file
+        #[arg(short, long)]
+        output_dir: Option<String>,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+        #[arg(short, long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Show how a configuration's times changed across recorded sessions
+    History {
+        run_name: String,
+        /// Defaults to the output_dir of the experiment file
+        #[arg(short, long)]
+        output_dir: Option<String>,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+    },
+    /// Add the profiles and cargo config customs needs to a project, keeping what's there
+    Init {
+        #[arg(short, long)]
+        cargo_dir: String,
+        /// Print the changes instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check that everything the experiment needs is installed and configured
+    Doctor {
+        #[arg(short, long)]
+        cargo_dir: String,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+    },
+    /// Put Cargo.toml and .cargo/config.toml back after a session crashed
+    Restore {
+        #[arg(short, long)]
+        cargo_dir: String,
+    },
+    /// Print every individual timing of a previous run, one row each
+    Export {
+        /// Defaults to the output_dir of the experiment file
+        #[arg(short, long)]
+        output_dir: Option<String>,
+        #[arg(short, long, default_value = "customs.toml")]
+        experiment: String,
+        #[arg(short, long, value_enum)]
+        format: ExportFormat,
+    },
+}
 
-    // Enable Parallel and O3
-    println!("Parallel and O3 Enabled");
-    enable_o3(&args.cargo_dir)?;
+#[derive(clap::Args, Clone, Debug)]
+struct CompareArgs {
+    /// Significance level below which a difference counts as real
+    #[arg(long, default_value_t = DEFAULT_ALPHA)]
+    alpha: f64,
+    /// Output directory of earlier results to check for regressions against.
+    /// Exits with an error if any run got significantly slower
+    #[arg(long)]
+    baseline: Option<String>,
+    /// How much slower than the baseline a run may get, e.g. 0.05 for 5%
+    #[arg(long, default_value_t = 0.05)]
+    tolerance: f64,
+}
 
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel_o3".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Table,
+    Markdown,
+    /// A single offline page with charts
+    Html,
+}
 
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel_o3".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ExportFormat {
+    Csv,
+    Jsonl,
+}
 
-    // Enable Parallel, O3, and Cranelift
-    println!("Parallel, O3, and Cranelift Enabled");
-    enable_cranelift(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel_o3_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+    match args.command {
+        Command::Run {
+            cargo_dir,
+            experiment,
+            dry_run: false,
+            compare,
+        } => run(&cargo_dir, &experiment, compare),
+        Command::Run {
+            cargo_dir,
+            experiment,
+            dry_run: true,
+            ..
+        } => plan(&cargo_dir, &experiment),
+        Command::Report {
+            output_dir,
+            experiment,
+            format,
+            compare,
+        } => report(
+            &resolve_output_dir(output_dir, &experiment)?,
+            format,
+            &compare,
+        ),
+        Command::Timings {
+            run_name,
+            output_dir,
+            experiment,
+            top,
+        } => {
+            let output_dir = resolve_output_dir(output_dir, &experiment)?;
+            let units = load_timings(&output_dir, &run_name)?;
+            let width = units
+                .iter()
+                .take(top)
+                .map(|u| u.unit.label().len())
+                .max()
+                .unwrap_or(0);
+            println!(
+                "{:<width$}  {:>8}  {:>8}  {:>8}  builds",
+                "unit", "mean", "min", "max"
+            );
+            for unit in units.iter().take(top) {
+                println!(
+                    "{:<width$}  {:>7.2}s  {:>7.2}s  {:>7.2}s  {}",
+                    unit.unit.label(),
+                    unit.mean,
+                    unit.min,
+                    unit.max,
+                    unit.samples
+                );
+            }
+            Ok(())
+        }
+        Command::Diff {
+            run_a,
+            run_b,
+            output_dir,
+            experiment,
+            top,
+        } => {
+            let output_dir = resolve_output_dir(output_dir, &experiment)?;
+            let a = load_timings(&output_dir, &run_a)?;
+            let b = load_timings(&output_dir, &run_b)?;
+            print_diff(&run_a, &run_b, &timings::diff(&a, &b), top);
+            Ok(())
+        }
+        Command::Restore { cargo_dir } => {
+            Snapshot::load(&cargo_dir)?.restore()?;
+            println!("Restored {cargo_dir}");
+            Ok(())
+        }
+        Command::Init { cargo_dir, dry_run } => {
+            let changes = match dry_run {
+                true => init::plan(&cargo_dir)?,
+                false => init::init(&cargo_dir)?,
+            };
+            if changes.is_empty() {
+                println!("{cargo_dir} is already set up");
+            }
+            for change in changes {
+                print!("{}", change.unified_diff());
+            }
+            Ok(())
+        }
+        Command::Doctor {
+            cargo_dir,
+            experiment,
+        } => doctor(&cargo_dir, &experiment),
+        Command::History {
+            run_name,
+            output_dir,
+            experiment,
+        } => print_history(&resolve_output_dir(output_dir, &experiment)?, &run_name),
+        Command::Export {
+            output_dir,
+            experiment,
+            format,
+        } => {
+            let runs = load_runs(&resolve_output_dir(output_dir, &experiment)?)?;
+            let rows = timing_rows(&runs);
+            match format {
+                ExportFormat::Csv => print!("{}", to_csv(&rows)),
+                ExportFormat::Jsonl => print!("{}", to_jsonl(&rows)?),
+            }
+            Ok(())
+        }
+    }
+}
 
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel_o3_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+fn resolve_output_dir(output_dir: Option<String>, experiment: &str) -> Result<String, Error> {
+    match output_dir {
+        Some(dir) => Ok(dir),
+        None => Ok(Experiment::load(experiment)?.output_dir),
+    }
+}
 
-    // Enable Parallel, O3, Cranelift, and Mold
-    println!("Parallel, O3, Cranelift, and Mold Enabled");
-    enable_mold(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel_o3_cranelift_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
+fn run(cargo_dir: &str, experiment: &str, mut compare: CompareArgs) -> Result<(), Error> {
+    println!("Running customs check on cargo!");
+    let mut experiment = Experiment::load(experiment)?;
+
+    // inspect() changes the working directory, so pin relative paths down first
+    let cargo_dir = fs::canonicalize(cargo_dir)?.to_string_lossy().to_string();
+    if let Some(baseline) = &compare.baseline {
+        compare.baseline = Some(fs::canonicalize(baseline)?.to_string_lossy().to_string());
+    }
+    fs::create_dir_all(&experiment.output_dir)?;
+    experiment.output_dir = fs::canonicalize(&experiment.output_dir)?
+        .to_string_lossy()
+        .to_string();
+    let picked = experiment.pick_hot_dependencies(&cargo_dir)?;
+    if !picked.is_empty() {
+        println!("Heaviest dependencies: {}", picked.join(", "));
+    }
+    experiment.read_project(&cargo_dir)?;
+    let environment = environment::capture(&cargo_dir)?;
+    environment::write(&experiment.output_dir, &environment)?;
+    let session = Session::capture(&cargo_dir, &environment)?;
+
+    //1. Remember the project exactly as we found it, and which toggles it has on
+    let guard = snapshot::protect(&cargo_dir)?;
+    let mut current = ToggleState::current(&experiment.resolve_toggles()?, &cargo_dir)?;
+    let mut announced = None;
+    let mut result_paths = Vec::new();
+
+    //2. Flip only what changed between configurations and run each scenario
+    for step in experiment.plan(&cargo_dir)? {
+        snapshot::locked(|| step.toggles.transition(&current, &cargo_dir))?;
+        current = step.toggles.clone();
+        if announced.as_ref() != Some(&current) {
+            let enabled: Vec<&str> = current.enabled().map(|s| s.name()).collect();
+            if enabled.is_empty() {
+                println!("Default Options");
+            } else {
+                println!("{} Enabled", enabled.join(", "));
+            }
+            announced = Some(current.clone());
+        }
+        inspect(&step.options)?;
+        annotate(
+            &step.options.result_path(),
+            &step.toggles.metadata(&step.scenario),
+        )?;
+        result_paths.push(step.options.result_path());
+    }
+
+    //3. Put the project back the way we found it
+    guard.finish()?;
+    let recorded = history::record(&experiment.output_dir, &session, &result_paths)?;
+    println!("Recorded this session in {recorded}");
+
+    //4. Summarize Results
+    report(&experiment.output_dir, Format::Table, &compare)
+}
 
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel_o3_cranelift_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+fn plan(cargo_dir: &str, experiment: &str) -> Result<(), Error> {
+    let mut experiment = Experiment::load(experiment)?;
+    let cargo_dir = fs::canonicalize(cargo_dir)?.to_string_lossy().to_string();
+    experiment.output_dir = std::env::current_dir()?
+        .join(&experiment.output_dir)
+        .to_string_lossy()
+        .to_string();
+    let picked = experiment.pick_hot_dependencies(&cargo_dir)?;
+    if !picked.is_empty() {
+        println!("# heaviest dependencies: {}\n", picked.join(", "));
+    }
+    experiment.read_project(&cargo_dir)?;
+
+    let mut dry_run = DryRun::new(&cargo_dir);
+    let mut current = ToggleState::current(&experiment.resolve_toggles()?, &cargo_dir)?;
+    for step in experiment.plan(&cargo_dir)? {
+        println!("# {}", step.options.run_name());
+        for change in dry_run.transition(&step.toggles, &current)? {
+            print!("{}", change.unified_diff());
+        }
+        current = step.toggles.clone();
+        println!("{}\n", command_line(&step.options));
+    }
+    Ok(())
+}
 
-    // Enable Parallel, Cranelift, and Mold
-    println!("Parallel, Cranelift, and Mold Enabled");
-    disable_o3(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel_cranelift_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
+fn load_timings(output_dir: &str, run_name: &str) -> Result<Vec<UnitTiming>, Error> {
+    timings::load(&format!("{output_dir}/{run_name}.timings")).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("no timings for {run_name}, was it run with `timings = true`? ({e})"),
+        )
+    })
+}
 
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel_cranelift_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+fn print_diff(run_a: &str, run_b: &str, diffs: &[UnitDiff], top: usize) {
+    let seconds = |t: Option<f64>| t.map_or("-".to_string(), |t| format!("{t:.2}s"));
+    let print = |title: &str, rows: Vec<&UnitDiff>| {
+        println!("{title}");
+        let width = rows.iter().map(|d| d.unit.label().len()).max().unwrap_or(0);
+        println!(
+            "  {:<width$}  {:>9}  {:>9}  {:>9}  {:>8}",
+            "unit", run_a, run_b, "change", "relative"
+        );
+        for d in rows {
+            println!(
+                "  {:<width$}  {:>9}  {:>9}  {:>+8.2}s  {:>8}",
+                d.unit.label(),
+                seconds(d.a),
+                seconds(d.b),
+                d.delta(),
+                d.relative()
+                    .map_or("-".to_string(), |r| format!("{:+.0}%", r * 100.0)),
+            );
+        }
+        println!();
+    };
+
+    let (a_total, b_total) = diffs.iter().fold((0.0, 0.0), |(a, b), d| {
+        (a + d.a.unwrap_or(0.0), b + d.b.unwrap_or(0.0))
+    });
+    println!("Total unit time: {a_total:.2}s in {run_a}, {b_total:.2}s in {run_b}\n");
+
+    print("Biggest absolute changes", diffs.iter().take(top).collect());
+    let mut relative: Vec<&UnitDiff> = diffs.iter().filter(|d| d.relative().is_some()).collect();
+    relative.sort_by(|x, y| {
+        let magnitude = |d: &UnitDiff| d.relative().unwrap_or(0.0).abs();
+        magnitude(y).total_cmp(&magnitude(x))
+    });
+    relative.truncate(top);
+    print("Biggest relative changes", relative);
+}
 
-    // Enable Parallel and Cranelift
-    println!("Parallel and Cranelift Enabled");
-    disable_mold(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command(" cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
+fn report(output_dir: &str, format: Format, compare: &CompareArgs) -> Result<(), Error> {
+    if !(0.0..1.0).contains(&compare.alpha) || compare.alpha == 0.0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "alpha must be between 0 and 1",
+        ));
+    }
+    let runs = load_runs(output_dir)?;
+    let summaries = summarize(&runs, compare.alpha);
+    let scaling = thread_scaling(&runs);
+    let environment = environment::load(output_dir)?;
+    match format {
+        Format::Table => {
+            print!("{}", format_table(&summaries));
+            print!("{}", format_scaling(&scaling));
+            if let Some(env) = &environment {
+                println!("environment");
+                for (label, value) in env.properties() {
+                    println!("  {label}: {value}");
+                }
+            }
+        }
+        Format::Markdown => print!(
+            "{}",
+            markdown_report(&summaries, &scaling, environment.as_ref())
+        ),
+        Format::Html => print!(
+            "{}",
+            html_report(&summaries, &scaling, environment.as_ref())
+        ),
+    }
+    let Some(baseline) = &compare.baseline else {
+        return Ok(());
+    };
+    if let (Some(ours), Some(theirs)) = (&environment, environment::load(baseline)?) {
+        warn_environment_differs(&theirs.differences(ours), "the baseline and these results");
+    }
+    check_regressions(&load_runs(baseline)?, &runs, compare)
+}
 
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel_cranelift".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+/// Prints every run that got slower than the baseline beyond the tolerance, and
+/// fails if any did so significantly. Goes to stderr so reports can still be redirected.
+fn check_regressions(baseline: &[Run], runs: &[Run], compare: &CompareArgs) -> Result<(), Error> {
+    let changes = compare_runs(baseline, runs, compare.alpha);
+    let undetectable: Vec<&Change> = changes
+        .iter()
+        .filter(|c| !c.is_detectable(compare.alpha))
+        .collect();
+    if let Some(change) = undetectable.first() {
+        let (before, after) = change.samples;
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} run(s) can't be checked for regressions: with {before} and {after} timings, like {}, no difference is significant at alpha {}. Set `runs` to at least {} or raise --alpha",
+                undetectable.len(),
+                change.run_name,
+                compare.alpha,
+                min_runs(compare.alpha)
+            ),
+        ));
+    }
+    let regressions: Vec<&Change> = changes
+        .iter()
+        .filter(|c| c.is_regression(compare.tolerance))
+        .collect();
+    eprintln!(
+        "Compared {} of {} runs against the baseline (tolerance {:.1}%, alpha {})",
+        changes.len(),
+        runs.len(),
+        compare.tolerance * 100.0,
+        compare.alpha
+    );
+    for change in changes.iter().filter(|c| c.slowdown() > compare.tolerance) {
+        eprintln!(
+            "  {}: {:.3}s -> {:.3}s ({:+.1}%){}",
+            change.run_name,
+            change.baseline_mean,
+            change.mean,
+            change.slowdown() * 100.0,
+            match change.comparison.significant {
+                true => "",
+                false => " (n.s.)",
+            }
+        );
+    }
+    match regressions.len() {
+        0 => {
+            eprintln!("No regressions");
+            Ok(())
+        }
+        n => Err(Error::other(format!("{n} run(s) regressed"))),
+    }
+}
 
-    // Enable Parallel and Mold
-    println!("Parallel and Mold Enabled");
-    disable_cranelift(&args.cargo_dir)?;
-    enable_mold(&args.cargo_dir)?;
-    let clean_clean = CargoCommandOptions::builder()
-        .prepare_command("cargo clean".to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("clean_parallel_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_clean)?;
+fn doctor(cargo_dir: &str, experiment: &str) -> Result<(), Error> {
+    let mut experiment = Experiment::load(experiment)?;
+    // A Cargo.toml that can't be read and missing timings to pick from are among the checks
+    let _ = experiment.read_project(cargo_dir);
+    let _ = experiment.pick_hot_dependencies(cargo_dir);
+    let checks = customs::doctor::check(&experiment, cargo_dir);
+    for check in &checks {
+        match &check.fix {
+            None => println!("ok    {}", check.description),
+            Some(fix) => println!("FAIL  {}\n      fix: {fix}", check.description),
+        }
+    }
+    match checks.iter().filter(|c| !c.passed()).count() {
+        0 => Ok(()),
+        n => Err(Error::other(format!("{n} check(s) failed"))),
+    }
+}
 
-    let clean_incremental = CargoCommandOptions::builder()
-        .prepare_command(sed_command.to_string())
-        .runs(args.runs)
-        .cargo_command("cargo leptos build".to_string())
-        .output_dir(args.output_dir.to_string())
-        .run_name("incremental_parallel_mold".to_string())
-        .compile_path(args.cargo_dir.to_string())
-        .build();
-    inspect(&clean_incremental)?;
+/// Comparing timings across toolchains or machines says little about the project itself.
+fn warn_environment_differs(differences: &[String], what: &str) {
+    if differences.is_empty() {
+        return;
+    }
+    eprintln!("Warning: {what} were measured in different environments:");
+    for difference in differences {
+        eprintln!("  {difference}");
+    }
+}
 
-    // Summarize Results
+fn print_history(output_dir: &str, run_name: &str) -> Result<(), Error> {
+    let entries = history::history(output_dir, run_name)?;
+    if entries.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "no session in {output_dir}/{} ran {run_name}",
+                history::HISTORY_DIR
+            ),
+        ));
+    }
+    println!(
+        "{:<23}  {:<15}  {:<12}  {:>8}  {:>8}  {:>7}  toolchain",
+        "date", "commit", "Cargo.lock", "mean", "stddev", "change"
+    );
+    let mut previous: Option<f64> = None;
+    let mut warnings = Vec::new();
+    let mut previous_environment: Option<(String, Environment)> = None;
+    for (dir, session, run) in &entries {
+        if let Some(env) = environment::load(dir)? {
+            if let Some((date, before)) = &previous_environment {
+                let differences = before.differences(&env);
+                if !differences.is_empty() {
+                    warnings.push((format!("{date} and {}", session.date()), differences));
+                }
+            }
+            previous_environment = Some((session.date(), env));
+        }
+        let r = &run.result;
+        println!(
+            "{:<23}  {:<15}  {:<12}  {:>7.3}s  {:>8}  {:>7}  {}",
+            session.date(),
+            session.short_commit(),
+            session
+                .cargo_lock
+                .as_deref()
+                .map_or("-", |h| &h[..h.len().min(12)]),
+            r.mean,
+            r.stddev.map_or("-".to_string(), |s| format!("{s:.3}s")),
+            previous.map_or("-".to_string(), |p| format!(
+                "{:+.1}%",
+                (r.mean / p - 1.0) * 100.0
+            )),
+            session.toolchain
+        );
+        previous = Some(r.mean);
+    }
+    for (sessions, differences) in warnings {
+        warn_environment_differs(&differences, &format!("the sessions of {sessions}"));
+    }
     Ok(())
 }
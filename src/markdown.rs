@@ -0,0 +1,95 @@
+use crate::environment::Environment;
+use crate::report::{threads_label, ScalingCurve, ScenarioSummary};
+use crate::results::RunMetadata;
+
+/// The enabled toggles of a run, or `default` when none are.
+pub fn toggle_label(metadata: &RunMetadata) -> String {
+    let enabled: Vec<&str> = metadata
+        .toggles
+        .iter()
+        .filter(|(_, on)| **on)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if enabled.is_empty() {
+        "default".to_string()
+    } else {
+        enabled.join(", ")
+    }
+}
+
+/// Renders a session as Markdown. The output only depends on its inputs, so
+/// regenerating a report from the same results produces an identical file.
+pub fn markdown_report(
+    summaries: &[ScenarioSummary],
+    scaling: &[ScalingCurve],
+    environment: Option<&Environment>,
+) -> String {
+    let mut out = String::from("# Customs benchmark report\n");
+
+    for summary in summaries {
+        out.push_str(&format!("\n## {}\n\n", summary.scenario));
+        match &summary.baseline {
+            Some(baseline) => out.push_str(&format!(
+                "Speedup is relative to `{}`, with a {:.0}% bootstrap confidence interval. \
+                 `(n.s.)` marks speedups that Welch's t-test and the Mann-Whitney U test \
+                 don't both find significant at {}.\n\n",
+                baseline.run_name,
+                (1.0 - summary.alpha) * 100.0,
+                summary.alpha
+            )),
+            None => out.push_str("No default configuration was run, so there is no speedup.\n\n"),
+        }
+        out.push_str("| Run | Toggles | Mean | Stddev | Min | Max | Median | Speedup | CI | p (Welch / MWU) |\n");
+        out.push_str("|---|---|--:|--:|--:|--:|--:|--:|--:|--:|\n");
+        for row in &summary.rows {
+            let r = &row.run.result;
+            out.push_str(&format!(
+                "| `{}` | {} | {:.3}s | {} | {:.3}s | {:.3}s | {:.3}s | {} | {} | {} |\n",
+                row.run.run_name,
+                toggle_label(&row.run.metadata),
+                r.mean,
+                r.stddev.map_or("-".to_string(), |s| format!("{s:.3}s")),
+                r.min,
+                r.max,
+                r.median,
+                row.speedup_label(),
+                row.ci_label(),
+                row.p_label(),
+            ));
+        }
+    }
+
+    if !scaling.is_empty() {
+        out.push_str("\n## Thread scaling\n\nSpeedup is relative to the same toggles without `-Z threads`.\n");
+    }
+    for curve in scaling {
+        out.push_str(&format!("\n### {}\n\n", curve.label()));
+        out.push_str("| Threads | Run | Mean | Speedup |\n");
+        out.push_str("|--:|---|--:|--:|\n");
+        for point in &curve.points {
+            out.push_str(&format!(
+                "| {} | `{}` | {:.3}s | {} |\n",
+                threads_label(point),
+                point.run_name,
+                point.mean,
+                point
+                    .speedup
+                    .map_or("-".to_string(), |s| format!("{s:.2}x")),
+            ));
+        }
+    }
+
+    out.push_str("\n## Environment\n\n");
+    match environment {
+        Some(env) => {
+            for (label, value) in env.properties() {
+                out.push_str(&format!("- {label}: {value}\n"));
+            }
+            out.push_str("\n```text\n");
+            out.push_str(env.rustc.trim());
+            out.push_str("\n```\n");
+        }
+        None => out.push_str("Not recorded for this session.\n"),
+    }
+    out
+}
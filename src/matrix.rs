@@ -0,0 +1,193 @@
+use crate::experiment::ToggleState;
+use crate::toggle::Toggle;
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Every on/off combination of a set of toggles, minus the ones pruned by rules.
+///
+/// A combination is dropped if it enables two toggles of the same [`Toggle::group`],
+/// or every toggle of any `exclude` rule. When `include` is non-empty, a combination
+/// is only kept if it enables every toggle of at least one `include` rule.
+/// Rules refer to toggles by [`Toggle::name`].
+#[derive(TypedBuilder)]
+pub struct Matrix {
+    toggles: Vec<Arc<dyn Toggle>>,
+    #[builder(default)]
+    include: Vec<Vec<String>>,
+    #[builder(default)]
+    exclude: Vec<Vec<String>>,
+}
+
+impl Matrix {
+    fn allowed(&self, state: &ToggleState) -> bool {
+        // Combinations never enable two toggles of a group to begin with
+        let matches = |rule: &Vec<String>| rule.iter().all(|name| state.is_enabled(name));
+        if self.exclude.iter().any(matches) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(matches)
+    }
+
+    /// The choices each combination makes: whether an ungrouped toggle is on, or
+    /// which toggle of a group is, if any. Each lists the indices of its toggles.
+    fn dimensions(&self) -> Vec<Vec<usize>> {
+        let mut dimensions: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+        for (i, toggle) in self.toggles.iter().enumerate() {
+            match dimensions
+                .iter_mut()
+                .find(|(group, _)| group.is_some() && *group == toggle.group())
+            {
+                Some((_, members)) => members.push(i),
+                None => dimensions.push((toggle.group(), vec![i])),
+            }
+        }
+        dimensions.into_iter().map(|(_, members)| members).collect()
+    }
+
+    /// The allowed combinations, starting from everything disabled and ordered so
+    /// that each step switches a single toggle, or one toggle of a group for
+    /// another, except where rules leave gaps.
+    pub fn states(&self) -> Vec<ToggleState> {
+        // Digit `d` of a dimension enables its toggle `d - 1`, 0 being all off.
+        // A reflected mixed-radix Gray code changes one digit by one at a time.
+        let dimensions = self.dimensions();
+        let mut digits = vec![0; dimensions.len()];
+        let mut ascending = vec![true; dimensions.len()];
+        let mut states = Vec::new();
+        loop {
+            let enabled: Vec<&str> = dimensions
+                .iter()
+                .zip(&digits)
+                .filter(|(_, digit)| **digit > 0)
+                .map(|(members, digit)| self.toggles[members[digit - 1]].name())
+                .collect();
+            let state = ToggleState::from_enabled(&self.toggles, &enabled);
+            if self.allowed(&state) {
+                states.push(state);
+            }
+
+            let Some(next) = (0..dimensions.len()).find(|&j| match ascending[j] {
+                true => digits[j] < dimensions[j].len(),
+                false => digits[j] > 0,
+            }) else {
+                return states;
+            };
+            // Every lower digit has reached its end and turns around
+            for direction in &mut ascending[..next] {
+                *direction = !*direction;
+            }
+            match ascending[next] {
+                true => digits[next] += 1,
+                false => digits[next] -= 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Error;
+
+    /// A toggle that only has a name and a group, `states` never touches files.
+    struct Named(&'static str, Option<&'static str>);
+
+    impl Toggle for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn file(&self) -> &str {
+            unreachable!()
+        }
+
+        fn plan(&self, _: bool, _: &str) -> Result<String, Error> {
+            unreachable!()
+        }
+
+        fn is_enabled_in(&self, _: &str) -> Result<bool, Error> {
+            unreachable!()
+        }
+
+        fn group(&self) -> Option<&str> {
+            self.1
+        }
+    }
+
+    fn toggles() -> Vec<Arc<dyn Toggle>> {
+        vec![
+            Arc::new(Named("mold", Some("linker"))),
+            Arc::new(Named("lld", Some("linker"))),
+            Arc::new(Named("o3", None)),
+            Arc::new(Named("cranelift", None)),
+        ]
+    }
+
+    fn rule(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn enabled(state: &ToggleState) -> Vec<&str> {
+        state.enabled().map(|t| t.name()).collect()
+    }
+
+    #[test]
+    fn states_cover_every_combination_once() {
+        let states = Matrix::builder().toggles(toggles()).build().states();
+        // No linker, mold or lld, times o3 on or off, times cranelift on or off
+        assert_eq!(states.len(), 3 * 2 * 2);
+        for (i, state) in states.iter().enumerate() {
+            assert!(!states[..i].contains(state), "{state:?} twice");
+            assert_eq!(state.conflict(), None);
+        }
+    }
+
+    #[test]
+    fn states_start_disabled_and_change_one_setting_at_a_time() {
+        let toggles = toggles();
+        let states = Matrix::builder().toggles(toggles.clone()).build().states();
+        assert_eq!(states[0], ToggleState::all_disabled(&toggles));
+        for pair in states.windows(2) {
+            let flips = pair[1].flips_from(&pair[0]);
+            // Swapping one linker for the other flips two toggles
+            let swap = enabled(&pair[0]).contains(&"mold") && enabled(&pair[1]).contains(&"lld")
+                || enabled(&pair[0]).contains(&"lld") && enabled(&pair[1]).contains(&"mold");
+            assert_eq!(flips, if swap { 2 } else { 1 }, "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn states_skip_excluded_combinations() {
+        let states = Matrix::builder()
+            .toggles(toggles())
+            .exclude(vec![rule(&["o3", "cranelift"])])
+            .build()
+            .states();
+        assert_eq!(states.len(), 3 * 3);
+        assert!(states
+            .iter()
+            .all(|s| !(s.is_enabled("o3") && s.is_enabled("cranelift"))));
+    }
+
+    #[test]
+    fn states_keep_only_included_combinations() {
+        let states = Matrix::builder()
+            .toggles(toggles())
+            .include(vec![rule(&["mold"]), rule(&["lld", "o3"])])
+            .build()
+            .states();
+        let mut names: Vec<Vec<&str>> = states.iter().map(enabled).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                vec!["lld", "o3"],
+                vec!["lld", "o3", "cranelift"],
+                vec!["mold"],
+                vec!["mold", "cranelift"],
+                vec!["mold", "o3"],
+                vec!["mold", "o3", "cranelift"],
+            ]
+        );
+    }
+}
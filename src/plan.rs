@@ -0,0 +1,71 @@
+use crate::experiment::ToggleState;
+use crate::read_project_file;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::io::Error;
+
+/// A rewrite of one project file that has not been written.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Change {
+    pub file: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl Change {
+    pub fn unified_diff(&self) -> String {
+        TextDiff::from_lines(&self.before, &self.after)
+            .unified_diff()
+            .header(&format!("a/{}", self.file), &format!("b/{}", self.file))
+            .to_string()
+    }
+}
+
+/// Plays toggle transitions against in-memory copies of the project's files,
+/// so the changes of a whole session can be previewed without touching them.
+pub struct DryRun {
+    cargo_dir: String,
+    files: BTreeMap<String, String>,
+}
+
+impl DryRun {
+    pub fn new(cargo_dir: &str) -> Self {
+        DryRun {
+            cargo_dir: cargo_dir.to_string(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    fn contents(&mut self, file: &str) -> Result<&mut String, Error> {
+        if !self.files.contains_key(file) {
+            let contents = read_project_file(&self.cargo_dir, file)?;
+            self.files.insert(file.to_string(), contents);
+        }
+        Ok(self.files.get_mut(file).unwrap())
+    }
+
+    /// What `to.transition(from, cargo_dir)` would change, one entry per file.
+    pub fn transition(
+        &mut self,
+        to: &ToggleState,
+        from: &ToggleState,
+    ) -> Result<Vec<Change>, Error> {
+        let mut changes: Vec<Change> = Vec::new();
+        for (toggle, on) in to.changes_from(from) {
+            let file = toggle.file();
+            let contents = self.contents(file)?;
+            let after = toggle.plan(on, contents)?;
+            let before = std::mem::replace(contents, after.clone());
+            match changes.iter_mut().find(|c| c.file == file) {
+                Some(change) => change.after = after,
+                None => changes.push(Change {
+                    file: file.to_string(),
+                    before,
+                    after,
+                }),
+            }
+        }
+        changes.retain(|c| c.before != c.after);
+        Ok(changes)
+    }
+}
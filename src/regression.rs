@@ -0,0 +1,48 @@
+use crate::results::Run;
+use crate::stats::{compare, min_p_value, Comparison};
+
+/// A run that exists in both the baseline and the current results.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Change {
+    pub run_name: String,
+    pub baseline_mean: f64,
+    pub mean: f64,
+    /// How many timings each side has.
+    pub samples: (usize, usize),
+    pub comparison: Comparison,
+}
+
+impl Change {
+    /// `mean / baseline mean - 1`, so anything above 0.0 is slower than the baseline.
+    pub fn slowdown(&self) -> f64 {
+        self.mean / self.baseline_mean - 1.0
+    }
+
+    /// Whether there are enough timings for the tests to ever be significant at `alpha`.
+    pub fn is_detectable(&self, alpha: f64) -> bool {
+        min_p_value(self.samples.0, self.samples.1) < alpha
+    }
+
+    /// Slower by more than `tolerance`, and significantly so.
+    pub fn is_regression(&self, tolerance: f64) -> bool {
+        self.slowdown() > tolerance && self.comparison.significant
+    }
+}
+
+/// Pairs up runs by `run_name` and tests each pair at the significance level `alpha`.
+/// Runs missing from either side are left out.
+pub fn compare_runs(baseline: &[Run], current: &[Run], alpha: f64) -> Vec<Change> {
+    current
+        .iter()
+        .filter_map(|run| {
+            let before = baseline.iter().find(|b| b.run_name == run.run_name)?;
+            Some(Change {
+                run_name: run.run_name.clone(),
+                baseline_mean: before.result.mean,
+                mean: run.result.mean,
+                samples: (before.result.times.len(), run.result.times.len()),
+                comparison: compare(&before.result.times, &run.result.times, alpha),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,265 @@
+use crate::results::Run;
+use crate::stats::{compare, Comparison};
+
+/// The significance level used when none is given.
+pub const DEFAULT_ALPHA: f64 = 0.05;
+
+/// A run alongside how it compares to the default configuration of its scenario.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Row {
+    pub run: Run,
+    /// `baseline mean / mean`, so anything above 1.0 is faster than the default.
+    pub speedup: Option<f64>,
+    /// How the individual timings compare to the baseline's, for every run but the baseline.
+    pub comparison: Option<Comparison>,
+}
+
+impl Row {
+    /// The speedup, marked `(n.s.)` when it isn't statistically significant.
+    pub fn speedup_label(&self) -> String {
+        match (self.speedup, self.comparison) {
+            (None, _) => "-".to_string(),
+            (Some(s), Some(c)) if !c.significant => format!("{s:.2}x (n.s.)"),
+            (Some(s), _) => format!("{s:.2}x"),
+        }
+    }
+
+    /// The bootstrap confidence interval of the speedup.
+    pub fn ci_label(&self) -> String {
+        match self.comparison.and_then(|c| c.speedup_ci) {
+            Some((low, high)) => format!("{low:.2}x-{high:.2}x"),
+            None => "-".to_string(),
+        }
+    }
+
+    /// The p-values of Welch's t-test and the Mann-Whitney U test.
+    pub fn p_label(&self) -> String {
+        let p = |p: Option<f64>| p.map_or("-".to_string(), |p| format!("{p:.3}"));
+        match self.comparison {
+            Some(c) => format!("{} / {}", p(c.welch_p), p(c.mann_whitney_p)),
+            None => "-".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScenarioSummary {
+    pub scenario: String,
+    pub baseline: Option<Run>,
+    /// Fastest first.
+    pub rows: Vec<Row>,
+    /// The significance level the rows were compared at.
+    pub alpha: f64,
+}
+
+/// Groups runs by scenario and ranks each group by mean time. Every run is tested
+/// against the baseline at the significance level `alpha`.
+pub fn summarize(runs: &[Run], alpha: f64) -> Vec<ScenarioSummary> {
+    let mut scenarios: Vec<&str> = runs.iter().map(|r| r.metadata.scenario.as_str()).collect();
+    scenarios.sort();
+    scenarios.dedup();
+
+    scenarios
+        .into_iter()
+        .map(|scenario| {
+            let mut group: Vec<&Run> = runs
+                .iter()
+                .filter(|r| r.metadata.scenario == scenario)
+                .collect();
+            group.sort_by(|a, b| {
+                a.result
+                    .mean
+                    .total_cmp(&b.result.mean)
+                    .then_with(|| a.run_name.cmp(&b.run_name))
+            });
+            let baseline = group.iter().find(|r| r.metadata.is_default()).copied();
+            let rows = group
+                .iter()
+                .map(|run| Row {
+                    run: (*run).clone(),
+                    speedup: baseline.map(|b| b.result.mean / run.result.mean),
+                    comparison: baseline
+                        .filter(|b| b.run_name != run.run_name)
+                        .map(|b| compare(&b.result.times, &run.result.times, alpha)),
+                })
+                .collect();
+            ScenarioSummary {
+                scenario: scenario.to_string(),
+                baseline: baseline.cloned(),
+                rows,
+                alpha,
+            }
+        })
+        .collect()
+}
+
+/// Renders the summaries as plain text tables for the terminal.
+pub fn format_table(summaries: &[ScenarioSummary]) -> String {
+    let mut out = String::new();
+    for summary in summaries {
+        let confidence = format!("{:.0}% ci", (1.0 - summary.alpha) * 100.0);
+        let header = [
+            "run",
+            "mean",
+            "stddev",
+            "min",
+            "max",
+            "median",
+            "speedup",
+            &confidence,
+            "p (welch / mwu)",
+        ]
+        .map(String::from);
+        let mut lines = vec![header];
+        for row in &summary.rows {
+            let r = &row.run.result;
+            lines.push([
+                row.run.run_name.clone(),
+                format!("{:.3}s", r.mean),
+                r.stddev.map_or("-".to_string(), |s| format!("{s:.3}s")),
+                format!("{:.3}s", r.min),
+                format!("{:.3}s", r.max),
+                format!("{:.3}s", r.median),
+                row.speedup_label(),
+                row.ci_label(),
+                row.p_label(),
+            ]);
+        }
+
+        let mut widths = [0; 9];
+        for line in &lines {
+            for (width, cell) in widths.iter_mut().zip(line) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        out.push_str(&format!("{}\n", summary.scenario));
+        for line in &lines {
+            let cells: Vec<String> = line
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(i, (cell, width))| match i {
+                    0 => format!("{cell:<width$}"),
+                    _ => format!("{cell:>width$}"),
+                })
+                .collect();
+            out.push_str(&format!("  {}\n", cells.join("  ")));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// One thread count of a scaling curve.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScalingPoint {
+    /// `None` for the run without `-Z threads`.
+    pub threads: Option<usize>,
+    pub run_name: String,
+    pub mean: f64,
+    /// Relative to the run without `-Z threads`, if there is one.
+    pub speedup: Option<f64>,
+}
+
+/// How build times scale with the frontend's thread count, for one scenario and
+/// one combination of the other toggles.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScalingCurve {
+    pub scenario: String,
+    /// The other toggles enabled in every run of the curve.
+    pub toggles: Vec<String>,
+    /// Ordered by thread count, without `-Z threads` first.
+    pub points: Vec<ScalingPoint>,
+}
+
+impl ScalingCurve {
+    pub fn label(&self) -> String {
+        match self.toggles.is_empty() {
+            true => self.scenario.clone(),
+            false => format!("{} + {}", self.scenario, self.toggles.join(", ")),
+        }
+    }
+}
+
+/// The thread count of a run of a thread-count sweep, from its `threads<N>` toggle.
+fn sweep_threads(toggle: &str) -> Option<usize> {
+    toggle.strip_prefix("threads")?.parse().ok()
+}
+
+/// Groups the runs of a thread-count sweep into one curve per scenario and
+/// combination of the other toggles. Runs outside of a sweep are left out.
+pub fn thread_scaling(runs: &[Run]) -> Vec<ScalingCurve> {
+    let mut curves: Vec<ScalingCurve> = Vec::new();
+    for run in runs {
+        let enabled = run.metadata.toggles.iter().filter(|(_, on)| **on);
+        let (threads, toggles): (Vec<&String>, Vec<&String>) = enabled
+            .map(|(name, _)| name)
+            .partition(|name| sweep_threads(name).is_some());
+        let toggles: Vec<String> = toggles.into_iter().cloned().collect();
+        let point = ScalingPoint {
+            threads: threads.first().and_then(|name| sweep_threads(name)),
+            run_name: run.run_name.clone(),
+            mean: run.result.mean,
+            speedup: None,
+        };
+        match curves
+            .iter_mut()
+            .find(|c| c.scenario == run.metadata.scenario && c.toggles == toggles)
+        {
+            Some(curve) => curve.points.push(point),
+            None => curves.push(ScalingCurve {
+                scenario: run.metadata.scenario.clone(),
+                toggles,
+                points: vec![point],
+            }),
+        }
+    }
+
+    curves.retain(|c| c.points.iter().any(|p| p.threads.is_some()));
+    for curve in &mut curves {
+        curve.points.sort_by_key(|p| p.threads);
+        let baseline = curve
+            .points
+            .iter()
+            .find(|p| p.threads.is_none())
+            .map(|p| p.mean);
+        for point in &mut curve.points {
+            point.speedup = baseline.map(|b| b / point.mean);
+        }
+    }
+    curves.sort_by(|a, b| {
+        a.scenario
+            .cmp(&b.scenario)
+            .then_with(|| a.toggles.cmp(&b.toggles))
+    });
+    curves
+}
+
+/// The thread count of a point, `off` for the run without `-Z threads`.
+pub fn threads_label(point: &ScalingPoint) -> String {
+    point.threads.map_or("off".to_string(), |n| n.to_string())
+}
+
+/// Renders the curves as text, with a bar per thread count that is longer the faster the build.
+pub fn format_scaling(curves: &[ScalingCurve]) -> String {
+    const BAR: f64 = 30.0;
+    let mut out = String::new();
+    for curve in curves {
+        let fastest = curve.points.iter().map(|p| p.mean).fold(f64::MAX, f64::min);
+        out.push_str(&format!("thread scaling: {}\n", curve.label()));
+        for point in &curve.points {
+            out.push_str(&format!(
+                "  {:>7}  {:>8}  {:>6}  {}\n",
+                threads_label(point),
+                format!("{:.3}s", point.mean),
+                point
+                    .speedup
+                    .map_or("-".to_string(), |s| format!("{s:.2}x")),
+                "#".repeat((fastest / point.mean * BAR).round() as usize),
+            ));
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,132 @@
+use crate::environment::ENVIRONMENT_FILE;
+use crate::history::SESSION_FILE;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// One entry of the `results` array hyperfine writes with `--export-json`.
+/// All times are in seconds.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub command: String,
+    pub mean: f64,
+    pub stddev: Option<f64>,
+    pub median: f64,
+    pub user: f64,
+    pub system: f64,
+    pub min: f64,
+    pub max: f64,
+    #[serde(default)]
+    pub times: Vec<f64>,
+    #[serde(default)]
+    pub exit_codes: Vec<Option<i32>>,
+    /// Per-iteration CPU times. Only the native runner records these; hyperfine
+    /// exports just the means in `user` and `system`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_times: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_times: Vec<f64>,
+}
+
+/// What customs knows about a run that hyperfine doesn't. Stored under the
+/// `customs` key of the exported JSON so the files stay self-describing.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub scenario: String,
+    pub toggles: BTreeMap<String, bool>,
+}
+
+impl RunMetadata {
+    /// Best guess for exports written before customs annotated them, relying on
+    /// run names being `<scenario>_<toggle>_<toggle>`.
+    pub fn from_run_name(run_name: &str) -> Self {
+        let mut parts = run_name.split('_');
+        let scenario = parts.next().unwrap_or_default().to_string();
+        RunMetadata {
+            scenario,
+            toggles: parts.map(|t| (t.to_string(), true)).collect(),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.toggles.values().all(|on| !on)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct HyperfineExport {
+    pub results: Vec<BenchmarkResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customs: Option<RunMetadata>,
+}
+
+/// A single configuration's results, as found in `<output_dir>/<run_name>.json`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Run {
+    pub run_name: String,
+    pub metadata: RunMetadata,
+    pub result: BenchmarkResult,
+}
+
+pub fn read_export(path: &Path) -> Result<HyperfineExport, Error> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {e}", path.to_string_lossy()),
+        )
+    })
+}
+
+/// Records the scenario and toggle state in an export hyperfine just wrote,
+/// leaving everything else in the file untouched.
+pub fn annotate(path: &str, metadata: &RunMetadata) -> Result<(), Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut export: serde_json::Value = serde_json::from_str(&contents)?;
+    if let serde_json::Value::Object(map) = &mut export {
+        map.insert("customs".to_string(), serde_json::to_value(metadata)?);
+    }
+    fs::write(path, serde_json::to_string_pretty(&export)?)
+}
+
+/// Loads every hyperfine export in `output_dir`, sorted by run name.
+pub fn load_runs(output_dir: &str) -> Result<Vec<Run>, Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(output_dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|n| n == ENVIRONMENT_FILE || n == SESSION_FILE)
+        {
+            continue;
+        }
+        if path.extension().is_some_and(|e| e == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut runs = Vec::new();
+    for path in paths {
+        let run_name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let export = read_export(&path)?;
+        let metadata = export
+            .customs
+            .unwrap_or_else(|| RunMetadata::from_run_name(&run_name));
+        let Some(result) = export.results.into_iter().next() else {
+            continue;
+        };
+        runs.push(Run {
+            run_name,
+            metadata,
+            result,
+        });
+    }
+    Ok(runs)
+}
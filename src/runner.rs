@@ -0,0 +1,161 @@
+use crate::results::{BenchmarkResult, HyperfineExport};
+use crate::CargoCommandOptions;
+use serde::Deserialize;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::process::{Command, ExitStatus};
+use std::time::Instant;
+
+/// What measures a run: hyperfine, or customs itself when hyperfine isn't installed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Runner {
+    #[default]
+    Hyperfine,
+    Native,
+}
+
+/// Timings of a single child process, in seconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Measurement {
+    pub wall: f64,
+    pub user: f64,
+    pub system: f64,
+    pub exit_code: Option<i32>,
+}
+
+/// CPU time used by every child process that has been waited for so far.
+#[cfg(unix)]
+fn children_cpu_time() -> (f64, f64) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: `usage` is a valid, writable rusage struct
+    unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) };
+    let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+    (seconds(usage.ru_utime), seconds(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn children_cpu_time() -> (f64, f64) {
+    (0.0, 0.0)
+}
+
+/// Runs `command` through the shell like hyperfine does, from `dir`.
+fn shell(command: &str, dir: &str) -> Result<ExitStatus, Error> {
+    #[cfg(unix)]
+    let mut cmd = Command::new("sh");
+    #[cfg(unix)]
+    cmd.arg("-c");
+    #[cfg(not(unix))]
+    let mut cmd = Command::new("cmd");
+    #[cfg(not(unix))]
+    cmd.arg("/C");
+    cmd.arg(command).current_dir(dir).status()
+}
+
+fn prepare(opts: &CargoCommandOptions) -> Result<(), Error> {
+    let prepare_command = opts.effective_prepare_command();
+    let status = shell(&prepare_command, &opts.compile_path)?;
+    if !status.success() {
+        return Err(Error::other(format!(
+            "prepare command `{prepare_command}` failed: {status}"
+        )));
+    }
+    Ok(())
+}
+
+/// Times one execution of the benchmarked command.
+pub fn measure(command: &str, dir: &str) -> Result<Measurement, Error> {
+    let (user_before, system_before) = children_cpu_time();
+    let start = Instant::now();
+    let status = shell(command, dir)?;
+    let wall = start.elapsed().as_secs_f64();
+    let (user_after, system_after) = children_cpu_time();
+    Ok(Measurement {
+        wall,
+        user: user_after - user_before,
+        system: system_after - system_before,
+        exit_code: status.code(),
+    })
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Summarizes measurements the way hyperfine does, with a sample standard deviation.
+pub fn summarize(command: &str, measurements: &[Measurement]) -> BenchmarkResult {
+    let times: Vec<f64> = measurements.iter().map(|m| m.wall).collect();
+    let user_times: Vec<f64> = measurements.iter().map(|m| m.user).collect();
+    let system_times: Vec<f64> = measurements.iter().map(|m| m.system).collect();
+
+    let mut sorted = times.clone();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    };
+    let mean_time = mean(&times);
+    let stddev = (times.len() > 1).then(|| {
+        let variance =
+            times.iter().map(|t| (t - mean_time).powi(2)).sum::<f64>() / (times.len() - 1) as f64;
+        variance.sqrt()
+    });
+
+    BenchmarkResult {
+        command: command.to_string(),
+        mean: mean_time,
+        stddev,
+        median,
+        user: mean(&user_times),
+        system: mean(&system_times),
+        min: sorted[0],
+        max: sorted[sorted.len() - 1],
+        exit_codes: measurements.iter().map(|m| m.exit_code).collect(),
+        times,
+        user_times,
+        system_times,
+    }
+}
+
+/// Benchmarks a run without hyperfine: the prepare command runs before every
+/// warmup and timed run, and the results are exported in hyperfine's format.
+pub fn run_native(opts: &CargoCommandOptions) -> Result<(), Error> {
+    if opts.runs == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "runs must be at least 1",
+        ));
+    }
+    let cargo_command = opts.effective_cargo_command();
+    println!("Benchmark: {cargo_command}");
+
+    for _ in 0..opts.warmup_runs {
+        prepare(opts)?;
+        shell(&cargo_command, &opts.compile_path)?;
+    }
+
+    let mut measurements = Vec::new();
+    for run in 1..=opts.runs {
+        prepare(opts)?;
+        let measurement = measure(&cargo_command, &opts.compile_path)?;
+        if measurement.exit_code != Some(0) {
+            return Err(Error::other(format!(
+                "`{cargo_command}` failed with exit code {:?}",
+                measurement.exit_code
+            )));
+        }
+        println!(
+            "  run {run}/{}: {:.3}s (user {:.3}s, system {:.3}s)",
+            opts.runs, measurement.wall, measurement.user, measurement.system
+        );
+        measurements.push(measurement);
+    }
+
+    let export = HyperfineExport {
+        results: vec![summarize(&cargo_command, &measurements)],
+        customs: None,
+    };
+    fs::write(opts.result_path(), serde_json::to_string_pretty(&export)?)
+}
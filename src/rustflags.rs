@@ -0,0 +1,346 @@
+use crate::{parse, table, target_triple};
+use std::io::Error;
+use std::ops::Range;
+use toml_edit::{value, Array, Document, Formatted, Item, Value};
+
+/// Which `rustflags` of a cargo config to manage.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Location {
+    /// `build.rustflags`, which cargo ignores for a target with `rustflags` of its own.
+    Build,
+    /// The `rustflags` cargo builds a target with, `None` being `build.target` or the host.
+    /// That is `target.<triple>.rustflags` when it's set and `build.rustflags` otherwise,
+    /// so flags always end up where they take effect. When neither is set,
+    /// `target.<triple>.rustflags` is created.
+    Target(Option<String>),
+}
+
+impl Location {
+    /// The triple of a target location, resolved against the config.
+    fn triple(&self, toml: &Document) -> Result<Option<String>, Error> {
+        match self {
+            Location::Build => Ok(None),
+            Location::Target(Some(triple)) => Ok(Some(triple.clone())),
+            Location::Target(None) => target_triple(toml).map(Some),
+        }
+    }
+
+    /// The table whose `rustflags` key is read and written: `None` for `build`,
+    /// the triple for `target.<triple>`.
+    fn resolve(&self, toml: &Document) -> Result<Option<String>, Error> {
+        let triple = self.triple(toml)?;
+        Ok(match triple {
+            Some(triple) if get(toml, None).is_some() && get(toml, Some(&triple)).is_none() => None,
+            triple => triple,
+        })
+    }
+}
+
+/// Replaces a string keeping the comments and whitespace around it.
+fn set_string(string: &mut Formatted<String>, contents: String) {
+    let decor = string.decor().clone();
+    *string = Formatted::new(contents);
+    *string.decor_mut() = decor;
+}
+
+fn get<'a>(toml: &'a Document, triple: Option<&str>) -> Option<&'a Item> {
+    match triple {
+        None => toml.get("build")?.get("rustflags"),
+        Some(triple) => toml.get("target")?.get(triple)?.get("rustflags"),
+    }
+}
+
+fn get_mut<'a>(toml: &'a mut Document, triple: Option<&str>) -> Option<&'a mut Item> {
+    // `Item::get_mut` would insert the keys it doesn't find, so go through the tables
+    let parent = match triple {
+        None => toml.get_mut("build")?,
+        Some(triple) => toml
+            .get_mut("target")?
+            .as_table_like_mut()?
+            .get_mut(triple)?,
+    };
+    parent.as_table_like_mut()?.get_mut("rustflags")
+}
+
+/// The tokens of a `rustflags` value, which cargo accepts as an array or a string.
+fn flags(item: Option<&Item>) -> Vec<String> {
+    match item {
+        Some(item) if item.is_str() => item
+            .as_str()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+        Some(item) => item
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+/// Splits tokens written as `-Cfoo` or `-Zfoo` into `-C foo`, so flags can be
+/// compared however they were written. Each part keeps the index of its token.
+fn split(tokens: &[String]) -> Vec<(&str, usize)> {
+    let mut split = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token
+            .strip_prefix("-C")
+            .or_else(|| token.strip_prefix("-Z"))
+        {
+            Some(rest) if !rest.is_empty() => {
+                split.push((&token[..2], i));
+                split.push((rest, i));
+            }
+            _ => split.push((token.as_str(), i)),
+        }
+    }
+    split
+}
+
+/// The tokens that make up `flags`, if they appear as whole tokens.
+fn find(tokens: &[String], flags: &[&str]) -> Option<Range<usize>> {
+    if flags.is_empty() {
+        return None;
+    }
+    let split = split(tokens);
+    (0..split.len().saturating_sub(flags.len() - 1)).find_map(|start| {
+        let window = &split[start..start + flags.len()];
+        let (first, last) = (window[0].1, window[flags.len() - 1].1);
+        let whole = (start == 0 || split[start - 1].1 != first)
+            && split
+                .get(start + flags.len())
+                .is_none_or(|(_, i)| *i != last);
+        (whole && window.iter().zip(flags).all(|((a, _), b)| a == b)).then_some(first..last + 1)
+    })
+}
+
+/// Whether `flags` are among the `rustflags` at `location`.
+pub fn has_flags(config_toml: &str, location: &Location, flags: &[&str]) -> Result<bool, Error> {
+    let toml = parse(config_toml)?;
+    let triple = location.resolve(&toml)?;
+    let tokens = self::flags(get(&toml, triple.as_deref()));
+    Ok(find(&tokens, flags).is_some())
+}
+
+/// Appends `flags` to the `rustflags` at `location`, unless they're there already.
+/// Every other flag is kept as it was written.
+pub fn plan_add_flags(
+    config_toml: &str,
+    location: &Location,
+    flags: &[&str],
+) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
+    let triple = location.resolve(&toml)?;
+    let tokens = self::flags(get(&toml, triple.as_deref()));
+    if find(&tokens, flags).is_some() {
+        return Ok(config_toml.to_string());
+    }
+    match get_mut(&mut toml, triple.as_deref()) {
+        Some(Item::Value(Value::String(s))) => {
+            let mut joined = s.value().trim_end().to_string();
+            for flag in flags {
+                joined.push(' ');
+                joined.push_str(flag);
+            }
+            set_string(s, joined.trim_start().to_string());
+        }
+        Some(Item::Value(Value::Array(array))) => {
+            for flag in flags {
+                array.push(*flag);
+            }
+        }
+        _ => {
+            let parent = match &triple {
+                None => table(toml.as_table_mut(), "build")?,
+                Some(triple) => table(table(toml.as_table_mut(), "target")?, triple)?,
+            };
+            parent.set_implicit(false);
+            parent.insert("rustflags", value(Array::from_iter(flags.iter().copied())));
+        }
+    }
+    Ok(toml.to_string())
+}
+
+/// Takes `flags` back out of the `rustflags` at `location`, leaving every other
+/// flag alone. The key is removed once nothing is left in it.
+pub fn plan_remove_flags(
+    config_toml: &str,
+    location: &Location,
+    flags: &[&str],
+) -> Result<String, Error> {
+    let mut toml = parse(config_toml)?;
+    let triple = location.resolve(&toml)?;
+    let mut tokens = self::flags(get(&toml, triple.as_deref()));
+    let Some(range) = find(&tokens, flags) else {
+        return Ok(config_toml.to_string());
+    };
+    tokens.drain(range.clone());
+    let parent = match &triple {
+        None => toml.get_mut("build"),
+        Some(triple) => toml
+            .get_mut("target")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|t| t.get_mut(triple)),
+    };
+    let Some(parent) = parent.and_then(Item::as_table_like_mut) else {
+        return Ok(config_toml.to_string());
+    };
+    if tokens.is_empty() {
+        parent.remove("rustflags");
+        // Drop the table adding the flags created, and `target` if nothing else is in it
+        if parent.is_empty() {
+            match &triple {
+                None => {
+                    toml.remove("build");
+                }
+                Some(triple) => {
+                    if let Some(targets) = toml.get_mut("target").and_then(Item::as_table_like_mut)
+                    {
+                        targets.remove(triple);
+                        if targets.is_empty() {
+                            toml.remove("target");
+                        }
+                    }
+                }
+            }
+        }
+        return Ok(toml.to_string());
+    }
+    match parent.get_mut("rustflags") {
+        Some(Item::Value(Value::Array(array))) => {
+            for i in range.clone().rev() {
+                array.remove(i);
+            }
+            // The new first flag would keep the space it had after a comma
+            if let (0, Some(first)) = (range.start, array.get_mut(0)) {
+                first.decor_mut().set_prefix("");
+            }
+        }
+        Some(Item::Value(Value::String(s))) => set_string(s, tokens.join(" ")),
+        _ => {}
+    }
+    Ok(toml.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    fn target() -> Location {
+        Location::Target(Some(TARGET.to_string()))
+    }
+
+    #[test]
+    fn keeps_the_users_flags() {
+        let config =
+            "[target.x86_64-unknown-linux-gnu]\nrustflags = [\"--cfg\", \"tokio_unstable\"]\n";
+        let added = plan_add_flags(config, &target(), &["-C", "link-arg=-fuse-ld=mold"]).unwrap();
+        assert_eq!(
+            added,
+            "[target.x86_64-unknown-linux-gnu]\nrustflags = [\"--cfg\", \"tokio_unstable\", \"-C\", \"link-arg=-fuse-ld=mold\"]\n"
+        );
+        let removed =
+            plan_remove_flags(&added, &target(), &["-C", "link-arg=-fuse-ld=mold"]).unwrap();
+        assert_eq!(removed, config);
+    }
+
+    #[test]
+    fn matches_flags_written_as_one_token() {
+        let config = "[build]\nrustflags = [\"-Zthreads=8\", \"--cfg\", \"foo\"]\n";
+        assert!(has_flags(config, &Location::Build, &["-Z", "threads=8"]).unwrap());
+        assert!(!has_flags(config, &Location::Build, &["-Z", "threads=4"]).unwrap());
+        assert_eq!(
+            plan_add_flags(config, &Location::Build, &["-Z", "threads=8"]).unwrap(),
+            config
+        );
+        assert_eq!(
+            plan_remove_flags(config, &Location::Build, &["-Z", "threads=8"]).unwrap(),
+            "[build]\nrustflags = [\"--cfg\", \"foo\"]\n"
+        );
+    }
+
+    #[test]
+    fn only_matches_whole_tokens() {
+        let config = "[build]\nrustflags = [\"-Ctarget-cpu=native\"]\n";
+        assert!(!has_flags(config, &Location::Build, &["-C", "target-cpu"]).unwrap());
+    }
+
+    #[test]
+    fn round_trips_string_rustflags() {
+        let config = "[build]\nrustflags = \"--cfg foo\" # ours\n";
+        let added = plan_add_flags(config, &Location::Build, &["-Z", "threads=8"]).unwrap();
+        assert_eq!(
+            added,
+            "[build]\nrustflags = \"--cfg foo -Z threads=8\" # ours\n"
+        );
+        assert!(has_flags(&added, &Location::Build, &["-Z", "threads=8"]).unwrap());
+        assert_eq!(
+            plan_remove_flags(&added, &Location::Build, &["-Z", "threads=8"]).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn removing_the_first_flags_keeps_the_formatting() {
+        let config =
+            "[build]\nrustflags = [\"-C\", \"link-arg=-fuse-ld=mold\", \"--cfg\", \"foo\"]\n";
+        assert_eq!(
+            plan_remove_flags(config, &Location::Build, &["-C", "link-arg=-fuse-ld=mold"]).unwrap(),
+            "[build]\nrustflags = [\"--cfg\", \"foo\"]\n"
+        );
+    }
+
+    #[test]
+    fn removes_the_key_once_empty_and_keeps_comments() {
+        let config = "# linker setup\n[build]\n# fast builds\njobs = 4\n";
+        let added = plan_add_flags(config, &Location::Build, &["-Z", "threads=8"]).unwrap();
+        assert!(added.starts_with("# linker setup\n[build]\n# fast builds\njobs = 4\n"));
+        assert_eq!(
+            plan_remove_flags(&added, &Location::Build, &["-Z", "threads=8"]).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn drops_the_tables_adding_created() {
+        let added = plan_add_flags("", &Location::Build, &["-Z", "threads=8"]).unwrap();
+        assert_eq!(added, "[build]\nrustflags = [\"-Z\", \"threads=8\"]\n");
+        assert_eq!(
+            plan_remove_flags(&added, &Location::Build, &["-Z", "threads=8"]).unwrap(),
+            ""
+        );
+        let other = "[target.aarch64-apple-darwin]\nlinker = \"clang\"\n";
+        let added = plan_add_flags(other, &target(), &["-Z", "threads=8"]).unwrap();
+        assert_eq!(
+            plan_remove_flags(&added, &target(), &["-Z", "threads=8"]).unwrap(),
+            other
+        );
+    }
+
+    #[test]
+    fn target_falls_back_to_build_rustflags() {
+        let config = "[build]\nrustflags = [\"--cfg\", \"foo\"]\n";
+        let added = plan_add_flags(config, &target(), &["-Z", "threads=8"]).unwrap();
+        assert_eq!(
+            added,
+            "[build]\nrustflags = [\"--cfg\", \"foo\", \"-Z\", \"threads=8\"]\n"
+        );
+        let empty = plan_add_flags("", &target(), &["-Z", "threads=8"]).unwrap();
+        assert_eq!(
+            empty,
+            "[target.x86_64-unknown-linux-gnu]\nrustflags = [\"-Z\", \"threads=8\"]\n"
+        );
+        assert_eq!(
+            plan_remove_flags(&empty, &target(), &["-Z", "threads=8"]).unwrap(),
+            ""
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Written into the project while a session is running and removed once it is restored.
+pub const SNAPSHOT_FILE: &str = ".customs-snapshot.json";
+
+/// The files customs toggles settings in, relative to the project directory.
+const PROTECTED_FILES: [&str; 2] = ["Cargo.toml", ".cargo/config.toml"];
+
+/// The original contents of every file customs may rewrite. `None` means the
+/// file did not exist and has to be removed again.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    cargo_dir: String,
+    files: BTreeMap<String, Option<String>>,
+}
+
+impl Snapshot {
+    /// Records the current state of the project and saves it to disk, so a crashed
+    /// session can still be recovered with [`Snapshot::load`].
+    pub fn take(cargo_dir: &str) -> Result<Snapshot, Error> {
+        let path = Path::new(cargo_dir).join(SNAPSHOT_FILE);
+        if path.exists() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "{} is left over from an interrupted session, run `customs restore` first",
+                    path.to_string_lossy()
+                ),
+            ));
+        }
+
+        let mut files = BTreeMap::new();
+        for file in PROTECTED_FILES {
+            let contents = match fs::read_to_string(Path::new(cargo_dir).join(file)) {
+                Ok(contents) => Some(contents),
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            files.insert(file.to_string(), contents);
+        }
+        let snapshot = Snapshot {
+            cargo_dir: cargo_dir.to_string(),
+            files,
+        };
+        fs::write(path, serde_json::to_string_pretty(&snapshot)?)?;
+        Ok(snapshot)
+    }
+
+    pub fn load(cargo_dir: &str) -> Result<Snapshot, Error> {
+        let contents = fs::read_to_string(Path::new(cargo_dir).join(SNAPSHOT_FILE))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Puts every file back exactly as it was and deletes the saved snapshot.
+    pub fn restore(&self) -> Result<(), Error> {
+        for (file, contents) in &self.files {
+            let path = Path::new(&self.cargo_dir).join(file);
+            match contents {
+                Some(contents) => fs::write(path, contents)?,
+                None if path.exists() => fs::remove_file(path)?,
+                None => {}
+            }
+        }
+        fs::remove_file(Path::new(&self.cargo_dir).join(SNAPSHOT_FILE))
+    }
+}
+
+static ACTIVE: Mutex<Option<Snapshot>> = Mutex::new(None);
+
+fn active() -> MutexGuard<'static, Option<Snapshot>> {
+    // A panic while a toggle was being written must not stop us from restoring
+    ACTIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn restore_active() -> Result<(), Error> {
+    match active().take() {
+        Some(snapshot) => snapshot.restore(),
+        None => Ok(()),
+    }
+}
+
+/// Restores the protected project when dropped, including while unwinding from
+/// a panic or an error returned with `?`.
+pub struct RestoreGuard(());
+
+impl RestoreGuard {
+    /// Restores the project and reports any error, instead of only logging it on drop.
+    pub fn finish(self) -> Result<(), Error> {
+        restore_active()
+    }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        if let Err(e) = restore_active() {
+            eprintln!("Failed to restore the project, run `customs restore`: {e}");
+        }
+    }
+}
+
+/// Snapshots the project and makes sure it is restored however the process ends,
+/// including on SIGINT and SIGTERM. Only one project can be protected at a time.
+pub fn protect(cargo_dir: &str) -> Result<RestoreGuard, Error> {
+    let mut slot = active();
+    if slot.is_some() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "another project is already protected",
+        ));
+    }
+    let snapshot = Snapshot::take(cargo_dir)?;
+    *slot = Some(snapshot);
+    drop(slot);
+
+    // Only the first call can install the handler, later ones reuse it
+    let _ = ctrlc::set_handler(|| {
+        if let Err(e) = restore_active() {
+            eprintln!("Failed to restore the project, run `customs restore`: {e}");
+        }
+        std::process::exit(130);
+    });
+    Ok(RestoreGuard(()))
+}
+
+/// Runs `f` while holding the snapshot lock, so a signal can't restore the
+/// project halfway through a change and have the rest of the change land on top.
+pub fn locked<T>(f: impl FnOnce() -> T) -> T {
+    let _lock = active();
+    f()
+}
@@ -0,0 +1,358 @@
+/// How a configuration's timings compare to its baseline's.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Comparison {
+    /// Two-sided p-value of Welch's t-test on the means.
+    pub welch_p: Option<f64>,
+    /// Two-sided p-value of the Mann-Whitney U test, exact for small samples without
+    /// ties and using the normal approximation otherwise.
+    pub mann_whitney_p: Option<f64>,
+    /// Bootstrap confidence interval of `baseline mean / mean` at `1 - alpha`.
+    pub speedup_ci: Option<(f64, f64)>,
+    /// Whether both tests reject "no difference" at `alpha`.
+    pub significant: bool,
+}
+
+const BOOTSTRAP_RESAMPLES: usize = 2000;
+/// Fixed so that reports are reproducible.
+const BOOTSTRAP_SEED: u64 = 0x5eed_c057_0e55;
+
+/// Tests whether `candidate` differs from `baseline`, at the significance level `alpha`.
+pub fn compare(baseline: &[f64], candidate: &[f64], alpha: f64) -> Comparison {
+    let welch_p = welch_t_test(baseline, candidate);
+    let mann_whitney_p = mann_whitney_u(baseline, candidate);
+    let significant =
+        matches!((welch_p, mann_whitney_p), (Some(w), Some(m)) if w < alpha && m < alpha);
+    Comparison {
+        welch_p,
+        mann_whitney_p,
+        speedup_ci: bootstrap_speedup_ci(baseline, candidate, 1.0 - alpha),
+        significant,
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn sample_variance(values: &[f64]) -> f64 {
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Two-sided p-value for the means of `a` and `b` being equal, without assuming
+/// equal variances. Needs at least two samples each and some variance.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let (va, vb) = (sample_variance(a) / na, sample_variance(b) / nb);
+    if va + vb == 0.0 {
+        return None;
+    }
+    let t = (mean(a) - mean(b)) / (va + vb).sqrt();
+    let df = (va + vb).powi(2) / (va.powi(2) / (na - 1.0) + vb.powi(2) / (nb - 1.0));
+    Some(incomplete_beta(df / 2.0, 0.5, df / (df + t * t)))
+}
+
+/// Two-sided p-value for `a` and `b` coming from the same distribution, with tie
+/// and continuity corrections.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let mut all: Vec<(f64, bool)> = a
+        .iter()
+        .map(|v| (*v, true))
+        .chain(b.iter().map(|v| (*v, false)))
+        .collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // Average the ranks of tied values
+    let n = all.len();
+    let mut rank_sum_a = 0.0;
+    let mut tie_correction = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        rank_sum_a += all[i..=j].iter().filter(|(_, in_a)| *in_a).count() as f64 * rank;
+        let tied = (j - i + 1) as f64;
+        tie_correction += tied.powi(3) - tied;
+        i = j + 1;
+    }
+
+    let (n1, n2, n) = (a.len() as f64, b.len() as f64, n as f64);
+    let u = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    if tie_correction == 0.0 && a.len() <= EXACT_U_MAX && b.len() <= EXACT_U_MAX {
+        return Some(exact_u_p(u as usize, a.len(), b.len()));
+    }
+    let mu = n1 * n2 / 2.0;
+    let sigma = (n1 * n2 / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)))).sqrt();
+    if sigma == 0.0 || sigma.is_nan() {
+        return None;
+    }
+    let z = ((u - mu).abs() - 0.5).max(0.0) / sigma;
+    Some(erfc(z / std::f64::consts::SQRT_2))
+}
+
+/// Up to this many samples per side, the exact distribution of U is used.
+const EXACT_U_MAX: usize = 20;
+
+/// How many of the orderings of `n1` and `n2` samples give each U, for U from 0 to `n1 * n2`.
+fn u_frequencies(n1: usize, n2: usize) -> Vec<f64> {
+    // frequencies[j] holds the distribution for i and j samples, building up i
+    let mut frequencies: Vec<Vec<f64>> = (0..=n2).map(|_| vec![1.0]).collect();
+    for i in 1..=n1 {
+        let mut next: Vec<Vec<f64>> = vec![vec![1.0]];
+        for j in 1..=n2 {
+            // The largest value is either one of the first sample, beating all j of
+            // the second, or one of the second, beating none
+            let mut f = vec![0.0; i * j + 1];
+            for (u, count) in frequencies[j].iter().enumerate() {
+                f[u + j] += count;
+            }
+            for (u, count) in next[j - 1].iter().enumerate() {
+                f[u] += count;
+            }
+            next.push(f);
+        }
+        frequencies = next;
+    }
+    frequencies.swap_remove(n2)
+}
+
+/// Two-sided p-value of U from the exact distribution, for samples without ties.
+fn exact_u_p(u: usize, n1: usize, n2: usize) -> f64 {
+    let frequencies = u_frequencies(n1, n2);
+    let total: f64 = frequencies.iter().sum();
+    let tail = u.min(n1 * n2 - u);
+    let p = 2.0 * frequencies[..=tail].iter().sum::<f64>() / total;
+    p.min(1.0)
+}
+
+/// The smallest p-value the Mann-Whitney U test can give for these sample sizes,
+/// reached when every sample of one side is below every sample of the other.
+pub fn min_p_value(n1: usize, n2: usize) -> f64 {
+    if n1 == 0 || n2 == 0 {
+        return 1.0;
+    }
+    if n1 <= EXACT_U_MAX && n2 <= EXACT_U_MAX {
+        return exact_u_p(0, n1, n2);
+    }
+    let (n1, n2) = (n1 as f64, n2 as f64);
+    let sigma = (n1 * n2 * (n1 + n2 + 1.0) / 12.0).sqrt();
+    erfc((n1 * n2 / 2.0 - 0.5) / sigma / std::f64::consts::SQRT_2)
+}
+
+/// The fewest runs per side with which the tests can find a difference at `alpha`.
+pub fn min_runs(alpha: f64) -> usize {
+    (2..)
+        .find(|n| min_p_value(*n, *n) < alpha)
+        .unwrap_or(usize::MAX)
+}
+
+/// SplitMix64, which is plenty for resampling and keeps the output deterministic.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn resample_mean(&mut self, values: &[f64]) -> f64 {
+        let sum: f64 = (0..values.len())
+            .map(|_| values[(self.next() % values.len() as u64) as usize])
+            .sum();
+        sum / values.len() as f64
+    }
+}
+
+/// Percentile bootstrap interval of `mean(baseline) / mean(candidate)`.
+pub fn bootstrap_speedup_ci(
+    baseline: &[f64],
+    candidate: &[f64],
+    confidence: f64,
+) -> Option<(f64, f64)> {
+    if baseline.len() < 2 || candidate.len() < 2 {
+        return None;
+    }
+    let mut rng = Rng(BOOTSTRAP_SEED);
+    let mut ratios: Vec<f64> = (0..BOOTSTRAP_RESAMPLES)
+        .map(|_| rng.resample_mean(baseline) / rng.resample_mean(candidate))
+        .collect();
+    ratios.sort_by(f64::total_cmp);
+    let tail = (1.0 - confidence) / 2.0;
+    let index = |q: f64| ((q * (ratios.len() - 1) as f64).round() as usize).min(ratios.len() - 1);
+    Some((ratios[index(tail)], ratios[index(1.0 - tail)]))
+}
+
+/// Lanczos approximation of ln Γ(x) for x > 0.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000_000_000_190_015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Continued fraction for the incomplete beta function (modified Lentz's method).
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        for numerator in [
+            m * (b - m) * x / ((a + m2 - 1.0) * (a + m2)),
+            -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// The regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Complementary error function, accurate to about 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * polynomial.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn welch_matches_known_p_value() {
+        let p = welch_t_test(&[1.0, 2.0, 3.0, 4.0, 5.0], &[2.0, 3.0, 4.0, 5.0, 9.0]).unwrap();
+        assert_close(p, 0.2937, 1e-4);
+    }
+
+    #[test]
+    fn welch_needs_two_samples_and_variance() {
+        assert_eq!(welch_t_test(&[1.0], &[2.0, 3.0]), None);
+        assert_eq!(welch_t_test(&[1.0, 1.0], &[2.0, 2.0]), None);
+    }
+
+    #[test]
+    fn mann_whitney_with_ties_uses_the_corrected_normal_approximation() {
+        let p = mann_whitney_u(&[1.0, 2.0, 3.0, 4.0, 5.0], &[2.0, 3.0, 4.0, 5.0, 9.0]).unwrap();
+        assert_close(p, 0.39761, 1e-4);
+    }
+
+    #[test]
+    fn mann_whitney_without_ties_is_exact() {
+        let p = mann_whitney_u(&[1.0, 5.0, 7.0, 9.0], &[2.0, 3.0, 4.0, 6.0, 8.0]).unwrap();
+        assert_close(p, 92.0 / 126.0, 1e-12);
+        let p = mann_whitney_u(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]).unwrap();
+        assert_close(p, 0.1, 1e-12);
+    }
+
+    #[test]
+    fn smallest_reachable_p_value() {
+        assert_close(min_p_value(3, 3), 0.1, 1e-12);
+        assert_close(min_p_value(4, 4), 2.0 / 70.0, 1e-12);
+        assert_eq!(min_p_value(0, 4), 1.0);
+        assert_eq!(min_runs(0.05), 4);
+        assert_eq!(min_runs(0.01), 5);
+    }
+
+    #[test]
+    fn special_functions_match_known_values() {
+        assert_close(erfc(0.0), 1.0, 1e-7);
+        assert_close(erfc(1.0), 0.157_299_207, 1e-7);
+        assert_close(erfc(-1.0), 1.842_700_793, 1e-7);
+        // I_0.4(2, 3) is the chance of at least 2 successes in 4 trials at 0.4
+        assert_close(incomplete_beta(2.0, 3.0, 0.4), 0.5248, 1e-7);
+        assert_close(incomplete_beta(3.5, 3.5, 0.5), 0.5, 1e-7);
+        assert_eq!(incomplete_beta(2.0, 3.0, 0.0), 0.0);
+        assert_eq!(incomplete_beta(2.0, 3.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn bootstrap_interval_contains_the_speedup_and_is_reproducible() {
+        let baseline = [3.0, 3.1, 2.9, 3.05, 2.95];
+        let candidate = [2.0, 2.1, 1.9, 2.05, 1.95];
+        let (low, high) = bootstrap_speedup_ci(&baseline, &candidate, 0.95).unwrap();
+        assert!(low < 1.5 && 1.5 < high, "{low}..{high}");
+        assert_eq!(
+            bootstrap_speedup_ci(&baseline, &candidate, 0.95),
+            Some((low, high))
+        );
+        assert_eq!(bootstrap_speedup_ci(&[], &candidate, 0.95), None);
+    }
+}
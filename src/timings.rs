@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Written into a run's timings directory once its reports have been aggregated.
+pub const UNITS_FILE: &str = "units.json";
+
+/// One entry of the `UNIT_DATA` table in a `cargo-timing-*.html` report.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct UnitData {
+    pub name: String,
+    pub version: String,
+    /// `todo` for compiling, `run-custom-build` for running a build script.
+    pub mode: String,
+    /// e.g. `""` for a library, ` build-script` or ` proj "bin"`.
+    pub target: String,
+    pub duration: f64,
+    #[serde(default)]
+    pub rmeta_time: Option<f64>,
+}
+
+/// What identifies a unit of compilation across builds.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct UnitKey {
+    pub name: String,
+    pub version: String,
+    pub target: String,
+    pub mode: String,
+}
+
+impl UnitKey {
+    pub fn label(&self) -> String {
+        let mut label = format!("{} v{}", self.name, self.version);
+        let target = self.target.trim();
+        if !target.is_empty() {
+            label.push_str(&format!(" ({target})"));
+        }
+        if self.mode == "run-custom-build" {
+            label.push_str(" [run]");
+        }
+        label
+    }
+}
+
+impl From<&UnitData> for UnitKey {
+    fn from(unit: &UnitData) -> Self {
+        UnitKey {
+            name: unit.name.clone(),
+            version: unit.version.clone(),
+            target: unit.target.clone(),
+            mode: unit.mode.clone(),
+        }
+    }
+}
+
+/// A unit's compile time across every build of a run, in seconds. Builds that
+/// didn't compile the unit, like most incremental ones, don't count towards it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct UnitTiming {
+    pub unit: UnitKey,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub samples: usize,
+}
+
+/// Pulls the unit table out of a cargo timings report.
+pub fn parse_report(html: &str) -> Result<Vec<UnitData>, Error> {
+    let marker = "const UNIT_DATA = ";
+    let start = html.find(marker).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "not a cargo timings report, UNIT_DATA is missing",
+        )
+    })?;
+    let mut values = serde_json::Deserializer::from_str(&html[start + marker.len()..])
+        .into_iter::<Vec<UnitData>>();
+    match values.next() {
+        Some(units) => Ok(units?),
+        None => Err(Error::new(ErrorKind::InvalidData, "UNIT_DATA is empty")),
+    }
+}
+
+/// The shell command that copies the reports of builds since `dir/.start` into `dir`.
+/// It runs before every prepare command, so a `cargo clean` can't delete them first.
+pub fn collect_command(dir: &str) -> String {
+    format!(
+        "find \"${{CARGO_TARGET_DIR:-target}}/cargo-timings\" -name 'cargo-timing-*.html' -newer '{dir}/.start' -exec cp {{}} '{dir}/' \\; 2>/dev/null; true"
+    )
+}
+
+/// Marks the start of a run, so only reports written after it are collected. Reports
+/// and averages left by an earlier session of the same run are removed first.
+pub fn start(dir: &str) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    for report in report_paths(dir)? {
+        fs::remove_file(report)?;
+    }
+    match fs::remove_file(Path::new(dir).join(UNITS_FILE)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::write(Path::new(dir).join(".start"), "")
+}
+
+/// Every report collected in `dir`, oldest first.
+pub fn report_paths(dir: &str) -> Result<Vec<std::path::PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with("cargo-timing-") && name.ends_with(".html") {
+            paths.push(path);
+        }
+    }
+    // The file names start with a timestamp
+    paths.sort();
+    Ok(paths)
+}
+
+/// Drops the reports of warmup builds, then averages every unit over the remaining
+/// builds and writes the result to [`UNITS_FILE`]. Slowest units come first.
+pub fn aggregate(dir: &str, warmup_runs: usize) -> Result<Vec<UnitTiming>, Error> {
+    let paths = report_paths(dir)?;
+    for warmup in paths.iter().take(warmup_runs) {
+        fs::remove_file(warmup)?;
+    }
+
+    let mut durations: BTreeMap<UnitKey, Vec<f64>> = BTreeMap::new();
+    for path in paths.iter().skip(warmup_runs) {
+        for unit in parse_report(&fs::read_to_string(path)?)? {
+            durations
+                .entry(UnitKey::from(&unit))
+                .or_default()
+                .push(unit.duration);
+        }
+    }
+
+    let mut timings: Vec<UnitTiming> = durations
+        .into_iter()
+        .map(|(unit, samples)| UnitTiming {
+            unit,
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(0.0, f64::max),
+            samples: samples.len(),
+        })
+        .collect();
+    timings.sort_by(|a, b| b.mean.total_cmp(&a.mean).then_with(|| a.unit.cmp(&b.unit)));
+
+    fs::write(
+        Path::new(dir).join(UNITS_FILE),
+        serde_json::to_string_pretty(&timings)?,
+    )?;
+    Ok(timings)
+}
+
+pub fn load(dir: &str) -> Result<Vec<UnitTiming>, Error> {
+    let contents = fs::read_to_string(Path::new(dir).join(UNITS_FILE))?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// A unit's mean compile time in two runs. `None` means the unit wasn't built in that run.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UnitDiff {
+    pub unit: UnitKey,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+impl UnitDiff {
+    /// Seconds gained (positive) or saved (negative) going from `a` to `b`.
+    pub fn delta(&self) -> f64 {
+        self.b.unwrap_or(0.0) - self.a.unwrap_or(0.0)
+    }
+
+    /// `b / a - 1`, only for units built in both runs.
+    pub fn relative(&self) -> Option<f64> {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) if a > 0.0 => Some(b / a - 1.0),
+            _ => None,
+        }
+    }
+}
+
+/// Lines up every unit of two runs, biggest absolute change first.
+pub fn diff(a: &[UnitTiming], b: &[UnitTiming]) -> Vec<UnitDiff> {
+    let mut units: BTreeMap<&UnitKey, UnitDiff> = BTreeMap::new();
+    for timing in a {
+        units
+            .entry(&timing.unit)
+            .or_insert_with(|| UnitDiff {
+                unit: timing.unit.clone(),
+                a: None,
+                b: None,
+            })
+            .a = Some(timing.mean);
+    }
+    for timing in b {
+        units
+            .entry(&timing.unit)
+            .or_insert_with(|| UnitDiff {
+                unit: timing.unit.clone(),
+                a: None,
+                b: None,
+            })
+            .b = Some(timing.mean);
+    }
+    let mut diffs: Vec<UnitDiff> = units.into_values().collect();
+    diffs.sort_by(|x, y| {
+        y.delta()
+            .abs()
+            .total_cmp(&x.delta().abs())
+            .then_with(|| x.unit.cmp(&y.unit))
+    });
+    diffs
+}
+
+/// The `n` packages that took longest to compile, leaving out `exclude`, e.g. the
+/// workspace's own. Build scripts don't count, since cargo builds them with
+/// `build-override` rather than the package's profile.
+pub fn heaviest_packages(units: &[UnitTiming], exclude: &[String], n: usize) -> Vec<String> {
+    let mut totals: BTreeMap<&str, f64> = BTreeMap::new();
+    for timing in units {
+        let build_script =
+            timing.unit.mode == "run-custom-build" || timing.unit.target.trim() == "build-script";
+        if !build_script && !exclude.contains(&timing.unit.name) {
+            *totals.entry(&timing.unit.name).or_default() += timing.mean;
+        }
+    }
+    let mut totals: Vec<(&str, f64)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    totals
+        .into_iter()
+        .take(n)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
@@ -0,0 +1,632 @@
+use crate::rustflags::{has_flags, plan_add_flags, plan_remove_flags, Location};
+use crate::{
+    dev_opt_level, linker, parse, plan_disable_cranelift, plan_disable_o3, plan_disable_parallel,
+    plan_enable_cranelift, plan_enable_o3, plan_enable_parallel, plan_remove_profile_setting,
+    plan_set_dev_opt_level, plan_set_linker, plan_set_profile_value, plan_unset_linker,
+    profile_value, read_project_file, rewrite, OptLevel, ProfileValue, CARGO_TOML, CONFIG_TOML,
+    DEFAULT_THREADS, MOLD_FLAGS,
+};
+use std::io::Error;
+use toml_edit::Item;
+
+/// A setting that can be switched on and off in a project's cargo configuration.
+///
+/// Toggles describe their change as a rewrite of a single file, which is what lets
+/// customs preview it with `--dry-run`. Implement this to add your own settings
+/// to a [`Matrix`](crate::matrix::Matrix).
+pub trait Toggle {
+    /// Short identifier used in run names, e.g. `mold`.
+    fn name(&self) -> &str;
+
+    /// The file the toggle lives in, relative to the project directory.
+    fn file(&self) -> &str;
+
+    /// What enabling (`on`) or disabling the toggle turns the contents of `file` into.
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error>;
+
+    /// Whether `contents` of `file` have the toggle switched on.
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error>;
+
+    /// Toggles sharing a group are alternatives to each other, like linkers. No
+    /// configuration enables more than one toggle of a group.
+    fn group(&self) -> Option<&str> {
+        None
+    }
+
+    fn enable(&self, cargo_dir: &str) -> Result<(), Error> {
+        rewrite(cargo_dir, self.file(), |c| self.plan(true, c))
+    }
+
+    fn disable(&self, cargo_dir: &str) -> Result<(), Error> {
+        rewrite(cargo_dir, self.file(), |c| self.plan(false, c))
+    }
+
+    fn is_enabled(&self, cargo_dir: &str) -> Result<bool, Error> {
+        self.is_enabled_in(&read_project_file(cargo_dir, self.file())?)
+    }
+}
+
+/// A linker other than the platform default, selected through the `rustflags` cargo
+/// builds the target with.
+pub struct Linker {
+    name: &'static str,
+    flags: &'static [&'static str],
+    program: Option<&'static str>,
+    /// The linker driver the flags need, set as `target.<triple>.linker`.
+    driver: Option<&'static str>,
+}
+
+impl Linker {
+    pub const MOLD: Linker = Linker {
+        name: "mold",
+        flags: &MOLD_FLAGS,
+        program: Some("mold"),
+        driver: None,
+    };
+    pub const LLD: Linker = Linker {
+        name: "lld",
+        flags: &["-C", "link-arg=-fuse-ld=lld"],
+        program: Some("ld.lld"),
+        driver: None,
+    };
+    pub const GOLD: Linker = Linker {
+        name: "gold",
+        flags: &["-C", "link-arg=-fuse-ld=gold"],
+        program: Some("ld.gold"),
+        driver: None,
+    };
+    /// Linked through clang, gcc doesn't know `--ld-path`.
+    pub const WILD: Linker = Linker {
+        name: "wild",
+        flags: &["-C", "link-arg=--ld-path=wild"],
+        program: Some("wild"),
+        driver: Some("clang"),
+    };
+    /// The `rust-lld` that ships with rustc, so no system lld is needed. Nightly only.
+    pub const RUST_LLD: Linker = Linker {
+        name: "rust-lld",
+        flags: &[
+            "-C",
+            "linker-features=+lld",
+            "-C",
+            "link-self-contained=+linker",
+            "-Z",
+            "unstable-options",
+        ],
+        program: None,
+        driver: None,
+    };
+
+    /// The executable that has to be installed for this linker, if any.
+    pub fn program(&self) -> Option<&'static str> {
+        self.program
+    }
+
+    /// The linker driver this linker has to be run through, if not the default one.
+    pub fn driver(&self) -> Option<&'static str> {
+        self.driver
+    }
+
+    /// The rustflags selecting this linker.
+    pub fn flags(&self) -> &'static [&'static str] {
+        self.flags
+    }
+}
+
+impl Toggle for Linker {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn file(&self) -> &str {
+        CONFIG_TOML
+    }
+
+    fn group(&self) -> Option<&str> {
+        Some("linker")
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        let contents = match (on, self.driver) {
+            (true, Some(driver)) => plan_set_linker(contents, driver)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {e}", self.name)))?,
+            (false, Some(driver)) => plan_unset_linker(contents, driver)?,
+            (_, None) => contents.to_string(),
+        };
+        match on {
+            true => plan_add_flags(&contents, &Location::Target(None), self.flags),
+            false => plan_remove_flags(&contents, &Location::Target(None), self.flags),
+        }
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        let driver = match self.driver {
+            Some(driver) => linker(contents)?.as_deref() == Some(driver),
+            None => true,
+        };
+        Ok(driver && has_flags(contents, &Location::Target(None), self.flags)?)
+    }
+}
+
+/// `opt-level` 1 for the project and 3 for its dependencies in `profile.dev`.
+pub struct O3;
+
+impl Toggle for O3 {
+    fn name(&self) -> &str {
+        "o3"
+    }
+
+    fn file(&self) -> &str {
+        CARGO_TOML
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        match on {
+            true => plan_enable_o3(contents),
+            false => plan_disable_o3(contents),
+        }
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        let toml = parse(contents)?;
+        let opt_level = |profile: Option<&Item>| {
+            profile
+                .and_then(|p| p.get("opt-level"))
+                .and_then(Item::as_integer)
+        };
+        let dev = toml.get("profile").and_then(|p| p.get("dev"));
+        let deps = dev.and_then(|d| d.get("package")).and_then(|p| p.get("*"));
+        Ok(opt_level(dev) == Some(1) && opt_level(deps) == Some(3))
+    }
+}
+
+/// One `opt-level` of a sweep over `profile.dev`, either for the workspace's own crates
+/// or for every dependency through `package."*"`. Disabling it goes back to the
+/// project's own level, or takes the setting out if the project has none.
+pub struct OptLevelSweep {
+    name: String,
+    dependencies: bool,
+    level: OptLevel,
+    original: Option<OptLevel>,
+}
+
+impl OptLevelSweep {
+    /// `profile.dev.opt-level`, named `opt-<level>`.
+    pub fn profile(level: OptLevel) -> Self {
+        OptLevelSweep {
+            name: format!("opt-{level}"),
+            dependencies: false,
+            level,
+            original: None,
+        }
+    }
+
+    /// `profile.dev.package."*".opt-level`, named `deps-opt-<level>`.
+    pub fn dependencies(level: OptLevel) -> Self {
+        OptLevelSweep {
+            name: format!("deps-opt-{level}"),
+            dependencies: true,
+            level,
+            original: None,
+        }
+    }
+
+    /// Disabling the toggle sets `original`, the level the project had, back.
+    pub fn restoring(self, original: Option<OptLevel>) -> Self {
+        OptLevelSweep { original, ..self }
+    }
+
+    fn package(&self) -> Option<&str> {
+        self.dependencies.then_some("*")
+    }
+}
+
+impl Toggle for OptLevelSweep {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn file(&self) -> &str {
+        CARGO_TOML
+    }
+
+    fn group(&self) -> Option<&str> {
+        match self.dependencies {
+            true => Some("deps-opt-level"),
+            false => Some("opt-level"),
+        }
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        match (on, self.original) {
+            (true, _) => plan_set_dev_opt_level(contents, self.package(), self.level),
+            (false, Some(original)) => plan_set_dev_opt_level(contents, self.package(), original),
+            (false, None) => {
+                plan_remove_profile_setting(contents, "dev", self.package(), "opt-level")
+            }
+        }
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        Ok(dev_opt_level(contents, self.package())? == Some(self.level))
+    }
+}
+
+/// An `opt-level` for a few named dependencies only, through `[profile.dev.package.<name>]`,
+/// so the ones that are slow at runtime get optimized without the cost of optimizing every
+/// dependency. Disabling it puts back the levels the project had for them.
+pub struct HotDependencies {
+    packages: Vec<String>,
+    level: OptLevel,
+    original: Vec<Option<OptLevel>>,
+}
+
+impl HotDependencies {
+    pub fn new(packages: Vec<String>, level: OptLevel) -> Self {
+        let original = vec![None; packages.len()];
+        HotDependencies {
+            packages,
+            level,
+            original,
+        }
+    }
+
+    /// Disabling the toggle sets `original`, the level each of the packages had in
+    /// the same order, back.
+    pub fn restoring(self, original: Vec<Option<OptLevel>>) -> Self {
+        HotDependencies { original, ..self }
+    }
+
+    pub fn packages(&self) -> &[String] {
+        &self.packages
+    }
+}
+
+impl Toggle for HotDependencies {
+    fn name(&self) -> &str {
+        "hot-deps"
+    }
+
+    fn file(&self) -> &str {
+        CARGO_TOML
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        let mut contents = contents.to_string();
+        for (package, original) in self.packages.iter().zip(&self.original) {
+            contents = match (on, original) {
+                (true, _) => plan_set_dev_opt_level(&contents, Some(package), self.level)?,
+                (false, Some(original)) => {
+                    plan_set_dev_opt_level(&contents, Some(package), *original)?
+                }
+                (false, None) => {
+                    plan_remove_profile_setting(&contents, "dev", Some(package), "opt-level")?
+                }
+            };
+        }
+        Ok(contents)
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        for package in &self.packages {
+            if dev_opt_level(contents, Some(package))? != Some(self.level) {
+                return Ok(false);
+            }
+        }
+        Ok(!self.packages.is_empty())
+    }
+}
+
+/// One value of a `Cargo.toml` profile setting like `debug` or `strip`, for the
+/// workspace's own crates or, through `package."*"`, for every dependency.
+/// Disabling it goes back to the project's own value, or takes the setting out again
+/// if the project has none, back to cargo's default for the profile.
+pub struct ProfileSetting {
+    name: String,
+    group: String,
+    profile: String,
+    dependencies: bool,
+    key: &'static str,
+    value: ProfileValue,
+    original: Option<ProfileValue>,
+}
+
+impl ProfileSetting {
+    /// Named `<key>-<value>`, e.g. `debug-limited`.
+    pub fn new(profile: &str, key: &'static str, value: ProfileValue) -> Self {
+        ProfileSetting {
+            name: format!("{key}-{value}"),
+            group: key.to_string(),
+            profile: profile.to_string(),
+            dependencies: false,
+            key,
+            value,
+            original: None,
+        }
+    }
+
+    /// Named `deps-<key>-<value>`, e.g. `deps-debug-0`.
+    pub fn dependencies(profile: &str, key: &'static str, value: ProfileValue) -> Self {
+        ProfileSetting {
+            name: format!("deps-{key}-{value}"),
+            group: format!("deps-{key}"),
+            dependencies: true,
+            ..ProfileSetting::new(profile, key, value)
+        }
+    }
+
+    /// Disabling the toggle sets `original`, the value the project had, back.
+    pub fn restoring(self, original: Option<ProfileValue>) -> Self {
+        ProfileSetting { original, ..self }
+    }
+
+    fn package(&self) -> Option<&str> {
+        self.dependencies.then_some("*")
+    }
+}
+
+impl Toggle for ProfileSetting {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn file(&self) -> &str {
+        CARGO_TOML
+    }
+
+    fn group(&self) -> Option<&str> {
+        Some(&self.group)
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        match on {
+            true => plan_set_profile_value(
+                contents,
+                &self.profile,
+                self.package(),
+                self.key,
+                &self.value,
+            ),
+            false => match &self.original {
+                Some(original) => plan_set_profile_value(
+                    contents,
+                    &self.profile,
+                    self.package(),
+                    self.key,
+                    original,
+                ),
+                None => {
+                    plan_remove_profile_setting(contents, &self.profile, self.package(), self.key)
+                }
+            },
+        }
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        let value = profile_value(contents, &self.profile, self.package(), self.key)?;
+        Ok(value.is_some_and(|v| v.is_equivalent(&self.value, self.key)))
+    }
+}
+
+/// The cranelift codegen backend for `profile.server-dev` in `.cargo/config.toml`.
+pub struct Cranelift;
+
+impl Toggle for Cranelift {
+    fn name(&self) -> &str {
+        "cranelift"
+    }
+
+    fn file(&self) -> &str {
+        CONFIG_TOML
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        match on {
+            true => plan_enable_cranelift(contents),
+            false => plan_disable_cranelift(contents),
+        }
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        let toml = parse(contents)?;
+        let backend = toml
+            .get("profile")
+            .and_then(|p| p.get("server-dev"))
+            .and_then(|p| p.get("codegen-backend"))
+            .and_then(Item::as_str);
+        Ok(backend == Some("cranelift"))
+    }
+}
+
+/// The parallel compiler frontend, through `-Z threads` in the `rustflags` cargo builds the target with.
+///
+/// Every thread count is its own toggle: `parallel` for the experiment's count,
+/// and `threads<N>` for each count of a sweep. They share a group since only one
+/// count can be set at a time.
+pub struct Parallel {
+    name: String,
+    threads: usize,
+}
+
+impl Parallel {
+    pub fn new(threads: usize) -> Self {
+        Parallel {
+            name: "parallel".to_string(),
+            threads,
+        }
+    }
+
+    /// One value of a thread-count sweep, named `threads<N>`.
+    pub fn sweep(threads: usize) -> Self {
+        Parallel {
+            name: format!("threads{threads}"),
+            threads,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+impl Default for Parallel {
+    fn default() -> Self {
+        Parallel::new(DEFAULT_THREADS)
+    }
+}
+
+impl Toggle for Parallel {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn group(&self) -> Option<&str> {
+        Some("threads")
+    }
+
+    fn file(&self) -> &str {
+        CONFIG_TOML
+    }
+
+    fn plan(&self, on: bool, contents: &str) -> Result<String, Error> {
+        match on {
+            true => plan_enable_parallel(contents, self.threads),
+            false => plan_disable_parallel(contents, self.threads),
+        }
+    }
+
+    fn is_enabled_in(&self, contents: &str) -> Result<bool, Error> {
+        let threads = format!("threads={}", self.threads);
+        has_flags(contents, &Location::Target(None), &["-Z", &threads])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config that names its target, so no test depends on the host's.
+    const CONFIG: &str = "[build]\ntarget = \"x86_64-unknown-linux-gnu\"\n";
+
+    /// Enables and disables `toggle`, checking it's on in between and that `contents`
+    /// come back unchanged.
+    fn round_trip(toggle: &dyn Toggle, contents: &str) -> String {
+        assert!(!toggle.is_enabled_in(contents).unwrap());
+        let on = toggle.plan(true, contents).unwrap();
+        assert!(toggle.is_enabled_in(&on).unwrap(), "not enabled in\n{on}");
+        assert_eq!(toggle.plan(false, &on).unwrap(), contents);
+        on
+    }
+
+    #[test]
+    fn linkers_round_trip() {
+        for linker in [
+            Linker::MOLD,
+            Linker::LLD,
+            Linker::GOLD,
+            Linker::WILD,
+            Linker::RUST_LLD,
+        ] {
+            round_trip(&linker, CONFIG);
+        }
+    }
+
+    #[test]
+    fn wild_sets_clang_as_the_linker_driver() {
+        let on = round_trip(&Linker::WILD, CONFIG);
+        assert_eq!(linker(&on).unwrap().as_deref(), Some("clang"));
+    }
+
+    #[test]
+    fn wild_keeps_a_clang_the_project_set() {
+        let config = format!("{CONFIG}\n[target.x86_64-unknown-linux-gnu]\nlinker = \"clang\"\n");
+        round_trip(&Linker::WILD, &config);
+    }
+
+    #[test]
+    fn wild_refuses_to_replace_another_linker() {
+        let config = format!("{CONFIG}\n[target.x86_64-unknown-linux-gnu]\nlinker = \"gcc\"\n");
+        assert!(Linker::WILD.plan(true, &config).is_err());
+    }
+
+    #[test]
+    fn rust_lld_passes_the_flags_rustc_accepts() {
+        let on = round_trip(&Linker::RUST_LLD, CONFIG);
+        assert!(on.contains(
+            r#"rustflags = ["-C", "linker-features=+lld", "-C", "link-self-contained=+linker", "-Z", "unstable-options"]"#
+        ));
+    }
+
+    const CARGO: &str = "[package]\nname = \"app\"\n\n[profile.dev]\nopt-level = 1\n";
+
+    #[test]
+    fn opt_level_sweeps_restore_the_projects_level() {
+        let sweep = OptLevelSweep::profile(OptLevel::Level(3)).restoring(Some(OptLevel::Level(1)));
+        round_trip(&sweep, CARGO);
+        // The project's own level is on before any toggle is.
+        let own = OptLevelSweep::profile(OptLevel::Level(1));
+        assert!(own.is_enabled_in(CARGO).unwrap());
+    }
+
+    #[test]
+    fn dependency_opt_level_sweeps_remove_the_override_they_added() {
+        let on = round_trip(&OptLevelSweep::dependencies(OptLevel::Level(2)), CARGO);
+        assert_eq!(dev_opt_level(&on, None).unwrap(), Some(OptLevel::Level(1)));
+    }
+
+    #[test]
+    fn dependency_opt_level_sweeps_restore_the_projects_override() {
+        let cargo = format!("{CARGO}\n[profile.dev.package.\"*\"]\nopt-level = 3\n");
+        let sweep =
+            OptLevelSweep::dependencies(OptLevel::Level(0)).restoring(Some(OptLevel::Level(3)));
+        round_trip(&sweep, &cargo);
+    }
+
+    #[test]
+    fn hot_dependencies_restore_the_projects_levels() {
+        let cargo = format!("{CARGO}\n[profile.dev.package.sqlx]\nopt-level = 2\n");
+        let packages = vec!["sqlx".to_string(), "image".to_string()];
+        let hot = HotDependencies::new(packages, OptLevel::Level(3))
+            .restoring(vec![Some(OptLevel::Level(2)), None]);
+        let on = round_trip(&hot, &cargo);
+        assert_eq!(
+            dev_opt_level(&on, Some("image")).unwrap(),
+            Some(OptLevel::Level(3))
+        );
+    }
+
+    #[test]
+    fn hot_dependencies_without_packages_are_never_enabled() {
+        let hot = HotDependencies::new(vec![], OptLevel::Level(3));
+        assert!(!hot.is_enabled_in(CARGO).unwrap());
+    }
+
+    fn limited() -> ProfileValue {
+        ProfileValue::String("limited".to_string())
+    }
+
+    #[test]
+    fn profile_settings_restore_the_projects_value() {
+        let cargo = format!("{CARGO}# Full backtraces\ndebug = \"full\"\n");
+        let debug = ProfileSetting::new("dev", "debug", limited())
+            .restoring(Some(ProfileValue::String("full".to_string())));
+        let on = round_trip(&debug, &cargo);
+        assert!(on.contains("# Full backtraces\ndebug = \"limited\"\n"));
+    }
+
+    #[test]
+    fn profile_settings_remove_the_value_they_added() {
+        round_trip(
+            &ProfileSetting::dependencies("dev", "debug", ProfileValue::Integer(0)),
+            CARGO,
+        );
+    }
+
+    #[test]
+    fn equivalent_profile_values_count_as_enabled() {
+        let cargo = format!("{CARGO}debug = 1\n");
+        let debug = ProfileSetting::new("dev", "debug", limited());
+        assert!(debug.is_enabled_in(&cargo).unwrap());
+        assert_eq!(debug.plan(true, &cargo).unwrap(), cargo);
+    }
+}